@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use strum::{EnumMessage, IntoEnumIterator};
 use strum_macros::{Display, EnumIter, EnumMessage, EnumString};
 
@@ -8,6 +9,8 @@ use strum_macros::{Display, EnumIter, EnumMessage, EnumString};
     Deserialize,
     Debug,
     PartialEq,
+    Eq,
+    Hash,
     Copy,
     Clone,
     PartialOrd,
@@ -17,36 +20,74 @@ use strum_macros::{Display, EnumIter, EnumMessage, EnumString};
     EnumMessage,
 )]
 pub enum Card {
+    #[strum(
+        message = "If you play this card, you may choose an opponent. If they win this round, you receive a token of affection as well."
+    )]
+    Jester,
+    #[strum(
+        message = "If an opponent attacks you with the Guard while you hold this card, they drop out instead. You fold this card and fetch a new one from the deck."
+    )]
+    Assassin,
     #[strum(
         message = "If you play this card, you may choose an opponent and attempt to guess their card. If you guess right they drop out of the game. You may not guess the Guard."
     )]
     Guard,
     #[strum(message = "If you play this card, you may choose an opponent and see their card.")]
     Priest,
+    #[strum(
+        message = "If you play this card, you may choose two players (one of them may be yourself) who exchange their cards. Afterwards you see the new card of the first chosen player."
+    )]
+    Cardinal,
     #[strum(
         message = "If you play this card, you may compare your other card against the card of an opponent. The one with the lower card is drops out of the game. If they are equal no one drops out."
     )]
     Baron,
+    #[strum(
+        message = "If you play this card, you may choose one or two opponents and see their cards."
+    )]
+    Baroness,
     #[strum(
         message = "If you play this card, you are protected against all forms of attack for a single round. If the opponets forget and attempt to attack you, they drop out."
     )]
     Maid,
+    #[strum(
+        message = "If you play this card, you may choose an opponent. The next card played that targets players must target this opponent."
+    )]
+    Sycophant,
     #[strum(
         message = "If you play this card, you may force an opponent to fold their card and fetch a new one from the deck."
     )]
     Prince,
+    #[strum(
+        message = "If this card lies among your folded cards at the end of the round, the value of your card is increased by one."
+    )]
+    Count,
     #[strum(
         message = "If you play this card, you may choose an opponent and exchange you other card with theirs."
     )]
     King,
+    #[strum(
+        message = "If this card lies among your folded cards when you drop out, you receive a token of affection."
+    )]
+    Constable,
     #[strum(
         message = "If you in addition to this card hold either Prince or King, you must play it instead of the King or Prince."
     )]
     Countess,
+    #[strum(
+        message = "If you play this card, you may compare your other card against the card of an opponent. The one with the higher card is drops out of the game. If they are equal no one drops out."
+    )]
+    DowagerQueen,
     #[strum(
         message = "You must never play this card. If you are force to fold this card by any means (for example if you opponent plays the prince), you drop out."
     )]
     Princess,
+    // the option of the opponent to fold their card and fetch a new one is not supported, it
+    // would need a decision outside of their turn
+    #[strum(
+        message = "If you play this card, you may choose an opponent and attempt to guess their card. If you guess right you receive a token of affection. You may not guess the Guard. The Princess wins against the Bishop whenever they are compared."
+    )]
+    Bishop,
 }
 
 use Card::*;
@@ -57,15 +98,53 @@ impl Card {
     }
 
     pub fn needs_guess(&self) -> bool {
-        matches!(self, Guard)
+        matches!(self, Guard | Bishop)
     }
 
     pub fn guessable() -> &'static [Card] {
-        &[Priest, Baron, Maid, Prince, King, Countess, Princess]
+        &[
+            Jester,
+            Assassin,
+            Priest,
+            Cardinal,
+            Baron,
+            Baroness,
+            Maid,
+            Sycophant,
+            Prince,
+            Count,
+            King,
+            Constable,
+            Countess,
+            DowagerQueen,
+            Princess,
+            Bishop,
+        ]
     }
 
     pub fn needs_opponent(&self) -> bool {
-        matches!(self, Guard | Priest | Baron | Prince | King)
+        matches!(
+            self,
+            Jester
+                | Guard
+                | Priest
+                | Cardinal
+                | Baron
+                | Baroness
+                | Sycophant
+                | Prince
+                | King
+                | DowagerQueen
+                | Bishop
+        )
+    }
+
+    pub fn needs_second_opponent(&self) -> bool {
+        matches!(self, Cardinal)
+    }
+
+    pub fn allows_second_opponent(&self) -> bool {
+        matches!(self, Cardinal | Baroness)
     }
 
     pub fn rule(&self) -> String {
@@ -98,7 +177,73 @@ impl Card {
         ]
     }
 
-    fn value(&self) -> u8 {
-        *self as u8 + 1
+    pub fn premium_deck() -> [Card; 32] {
+        [
+            Card::Jester,
+            Card::Assassin,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Guard,
+            Card::Priest,
+            Card::Priest,
+            Card::Cardinal,
+            Card::Cardinal,
+            Card::Baron,
+            Card::Baron,
+            Card::Baroness,
+            Card::Baroness,
+            Card::Maid,
+            Card::Maid,
+            Card::Sycophant,
+            Card::Sycophant,
+            Card::Prince,
+            Card::Prince,
+            Card::Count,
+            Card::Count,
+            Card::King,
+            Card::Constable,
+            Card::Countess,
+            Card::DowagerQueen,
+            Card::Princess,
+            Card::Bishop,
+        ]
+    }
+
+    /// The classic deck only suffices for up to four players.
+    pub fn deck_for(player_count: usize) -> Vec<Card> {
+        if player_count <= 4 {
+            Card::deck().to_vec()
+        } else {
+            Card::premium_deck().to_vec()
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            Jester | Assassin => 0,
+            Guard => 1,
+            Priest | Cardinal => 2,
+            Baron | Baroness => 3,
+            Maid | Sycophant => 4,
+            Prince | Count => 5,
+            King | Constable => 6,
+            Countess | DowagerQueen => 7,
+            Princess => 8,
+            Bishop => 9,
+        }
+    }
+
+    /// Compares the cards by value, except that the Princess wins against the Bishop
+    pub fn compare(&self, other: &Card) -> Ordering {
+        match (self, other) {
+            (Princess, Bishop) => Ordering::Greater,
+            (Bishop, Princess) => Ordering::Less,
+            _ => self.value().cmp(&other.value()),
+        }
     }
 }
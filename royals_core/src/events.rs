@@ -1,13 +1,17 @@
 use crate::card::Card;
 use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
 
 pub type ActionId = usize;
 pub type PlayerId = usize;
+/// Sequence number of the questions a player is asked, answers name the turn they are meant for
+pub type Turn = u64;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Play {
     pub card: Card,
     pub opponent: Option<PlayerId>,
+    pub second_opponent: Option<PlayerId>,
     pub guess: Option<Card>,
 }
 
@@ -17,47 +21,275 @@ pub enum Action {
     Play(Play),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum GameEvent {
+    /// The complete log, sent first and whenever the client needs to catch up
     Notify(NotifyEvent),
+    /// The events which are new or changed since the last notification
+    Update(LogUpdate),
     ObtainAction(ObtainActionEvent),
+    /// Rooms that can be joined, sent to clients that are not in a room
+    RoomList(Vec<RoomInfo>),
+    /// Current state of the room the client is in
+    RoomUpdate(RoomInfo),
+    RoomRejected(String),
+    /// The action of the client was not taken, the question is asked again if it is still open
+    ActionRejected(ActionError),
+    /// The client got a seat in the game that started, the token reclaims it after the
+    /// connection was lost
+    Seated(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ActionError {
+    /// The action was meant for a turn that is over
+    Stale(Turn),
+    /// The client has nothing to decide right now
+    NotYourTurn,
+    /// The action is not one of those the client was offered
+    Invalid(ActionId),
+    /// The action submitted by value is not valid in the current turn
+    NotValid(Action),
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::Stale(turn) => write!(f, "Turn {} is over already", turn),
+            ActionError::NotYourTurn => write!(f, "It is not your turn"),
+            ActionError::Invalid(action_id) => write!(f, "Action {} is not valid", action_id),
+            ActionError::NotValid(_) => write!(f, "The action is not valid in this turn"),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomSettings {
+    /// Number of players at the table
+    pub seats: usize,
+    /// Seats not taken by humans are filled with computer players when the game starts
+    pub fill_with_bots: bool,
+    /// Private rooms are not listed and can only be joined with their code
+    pub private: bool,
+    /// Seconds a player has for each turn, unlimited if not set
+    pub turn_timeout_secs: Option<u64>,
+    pub timeout_action: TimeoutAction,
+    /// Strength of the computer players filling seats or taking over
+    pub bot_level: BotLevel,
+    /// A computer player takes over the seat of a disconnected player until the next round
+    pub bot_takeover: bool,
+    /// Seconds spectators watching with every hand revealed lag behind, not allowed if not set
+    pub omniscient_delay_secs: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum BotLevel {
+    /// Plays randomly
+    Easy,
+    /// Counts cards
+    #[default]
+    Medium,
+    /// Searches the game tree
+    Hard,
+}
+
+/// What happens when a player runs out of time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum TimeoutAction {
+    /// A computer player chooses the action
+    #[default]
+    BotMove,
+    GiveUp,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            seats: 4,
+            fill_with_bots: true,
+            private: false,
+            turn_timeout_secs: None,
+            timeout_action: TimeoutAction::BotMove,
+            bot_level: BotLevel::Medium,
+            bot_takeover: true,
+            omniscient_delay_secs: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub host: String,
+    pub players: Vec<String>,
+    pub settings: RoomSettings,
+    pub started: bool,
+    /// Code to share with friends, only sent to members of a private room
+    pub code: Option<String>,
+    pub spectators: Vec<String>,
+    /// The client the update is sent to watches rather than plays
+    pub spectating: bool,
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
 pub struct NotifyEvent {
     pub players: Vec<String>,
     pub game_log: Vec<Event>,
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+impl NotifyEvent {
+    /// The event bringing a client which knows this state up to `next`, if anything changed
+    pub fn update_to(&self, next: &NotifyEvent) -> Option<GameEvent> {
+        if self.players != next.players {
+            return Some(GameEvent::Notify(next.clone()));
+        }
+        let seq = self
+            .game_log
+            .iter()
+            .zip(&next.game_log)
+            .take_while(|(known, new)| known == new)
+            .count();
+        if seq == self.game_log.len() && seq == next.game_log.len() {
+            return None;
+        }
+        Some(GameEvent::Update(LogUpdate {
+            seq,
+            events: next.game_log[seq..].to_vec(),
+        }))
+    }
+
+    /// Applies a notification or an update, other events are ignored
+    pub fn apply(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Notify(notify) => self.clone_from(notify),
+            GameEvent::Update(update) => {
+                self.game_log.truncate(update.seq);
+                self.game_log.extend(update.events.iter().cloned());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Events of the log starting at sequence number `seq`, which is their index in the log. They
+/// replace the events the client has from there on, as hidden cards are revealed at the end of
+/// a round.
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LogUpdate {
+    pub seq: usize,
+    pub events: Vec<Event>,
+}
+
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
 pub struct ObtainActionEvent {
-    pub players: Vec<String>,
-    pub game_log: Vec<Event>,
+    pub turn: Turn,
     pub valid_actions: Vec<Action>,
+    /// Time left until an action is chosen on behalf of the player, counted from receiving the
+    /// question
+    pub time_left: Option<Duration>,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct ClientEvent {
-    pub action_id: usize,
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ClientEvent {
+    ListRooms,
+    CreateRoom(String, RoomSettings),
+    JoinRoom(String),
+    JoinWithCode(String),
+    /// Watches the game in the room, seeing every hand if the flag is set and the room allows it
+    Spectate(String, bool),
+    LeaveRoom,
+    /// Only the host of a room may start the game
+    StartGame,
+    /// The chosen one of the valid actions offered in the given turn
+    Action(Turn, ActionId),
+    /// An action given by value, taken if it is valid in the given turn
+    Submit(Turn, Action),
+    /// Takes the seat the token was handed out for again
+    Reclaim(String),
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Event {
+    /// The seed of the match, which reproduces the seating and the decks of all rounds
+    MatchStarted(u64),
     Play(PlayerId, Play),
     Fold(PlayerId, Card, String),
     PickUp(PlayerId, Option<Card>, usize),
     DropOut(PlayerId),
     LearnedCard(PlayerId, Option<Card>),
-    Winner(Vec<PlayerId>),
+    Winner(Vec<PlayerId>, Vec<usize>),
+    RoundStarted(usize, PlayerId, u64),
+    TokenAwarded(PlayerId, usize),
+    MatchWinner(Vec<PlayerId>),
+    BurnFaceDown(Option<Card>),
+    BurnFaceUp(Card),
+    /// The player ran out of time and the following action was chosen for them
+    TurnTimedOut(PlayerId),
+    /// A computer player took over the seat of a player who left
+    SeatTakenOver(PlayerId),
+    /// The player is back at their seat
+    SeatReturned(PlayerId),
 }
 
 #[derive(PartialEq)]
 pub enum EventVisibility {
     Public,
     Private(PlayerId),
+    Hidden,
 }
 
 pub struct EventEntry {
     pub visibility: EventVisibility,
     pub event: Event,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        card::Card,
+        events::{Event, GameEvent, LogUpdate, NotifyEvent},
+    };
+
+    fn notify(game_log: Vec<Event>) -> NotifyEvent {
+        NotifyEvent {
+            players: vec!["Foo".to_string(), "Bar".to_string()],
+            game_log,
+        }
+    }
+
+    #[test]
+    fn update_to_should_only_contain_new_and_revealed_events() {
+        let known = notify(vec![Event::PickUp(0, None, 10)]);
+        let appended = notify(vec![Event::PickUp(0, None, 10), Event::DropOut(1)]);
+        let revealed = notify(vec![Event::PickUp(0, Some(Card::Guard), 10)]);
+
+        assert!(known.update_to(&known).is_none());
+        assert!(matches!(
+            NotifyEvent::default().update_to(&known),
+            Some(GameEvent::Notify(_))
+        ));
+        assert!(matches!(
+            known.update_to(&appended),
+            Some(GameEvent::Update(LogUpdate { seq: 1, .. }))
+        ));
+        assert!(matches!(
+            appended.update_to(&revealed),
+            Some(GameEvent::Update(LogUpdate { seq: 0, .. }))
+        ));
+    }
+
+    #[test]
+    fn apply_should_catch_up_with_the_update() {
+        let mut client = notify(vec![Event::PickUp(0, None, 10)]);
+        let server = notify(vec![
+            Event::PickUp(0, Some(Card::Guard), 10),
+            Event::DropOut(1),
+        ]);
+
+        client.apply(&client.update_to(&server).unwrap());
+
+        assert_eq!(client, server);
+    }
+}
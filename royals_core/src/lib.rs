@@ -1,3 +1,4 @@
 pub mod card;
 pub mod events;
+pub mod protocol;
 pub mod user_name;
@@ -0,0 +1,187 @@
+use crate::events::{ClientEvent, GameEvent};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the events exchanged, to be raised whenever one of them changes
+pub const PROTOCOL_VERSION: u32 = 9;
+
+/// Version of the envelope and the handshake. As long as it stays the same, clients of another
+/// protocol version can still be told why they are not let in.
+pub const ENVELOPE_VERSION: u32 = 2;
+
+/// Netcode protocol id, connections of other games or envelope versions are refused right away
+pub const PROTOCOL_ID: u64 = u64::from_be_bytes(*b"ROYALS\0\0") + ENVELOPE_VERSION as u64;
+
+/// Why a message or the user data of a connection can not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProtocolError {
+    Malformed(String),
+    VersionMismatch(u32),
+    InvalidUsername(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Malformed(e) => write!(f, "Malformed message: {}", e),
+            ProtocolError::VersionMismatch(version) => write!(
+                f,
+                "Protocol version {} is not supported, the server speaks version {}",
+                version, PROTOCOL_VERSION
+            ),
+            ProtocolError::InvalidUsername(e) => write!(f, "Invalid username: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Encoding of the messages after the handshake, which itself is always sent as JSON
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum Codec {
+    #[default]
+    Json,
+    Bincode,
+}
+
+/// Supported codecs, the preferred one first
+pub const CODECS: &[Codec] = &[Codec::Bincode, Codec::Json];
+
+impl Codec {
+    /// The preferred codec among the ones offered by the client
+    pub fn negotiate(offered: &[Codec]) -> Codec {
+        CODECS
+            .iter()
+            .find(|codec| offered.contains(codec))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn serialize<M: Serialize>(self, message: &M) -> Vec<u8> {
+        match self {
+            Codec::Json => serde_json::to_vec(message).unwrap_or_default(),
+            Codec::Bincode => bincode_options().serialize(message).unwrap_or_default(),
+        }
+    }
+
+    fn deserialize<M: DeserializeOwned>(self, bytes: &[u8]) -> Result<M, ProtocolError> {
+        let malformed = |e: &dyn fmt::Display| ProtocolError::Malformed(e.to_string());
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| malformed(&e)),
+            Codec::Bincode => bincode_options()
+                .deserialize(bytes)
+                .map_err(|e| malformed(&e)),
+        }
+    }
+}
+
+/// Variable length integers keep the many small ids and counts of the log at a byte each. The
+/// header is read on its own, so trailing bytes are fine.
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().allow_trailing_bytes()
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Message<T> {
+    /// First message of the client with the codecs it supports, the server answers with a
+    /// welcome naming the codec of all further messages or a rejection
+    Hello(Vec<Codec>),
+    Welcome(Codec),
+    Rejected(String),
+    Event(T),
+}
+
+pub type ClientMessage = Message<ClientEvent>;
+pub type ServerMessage = Message<GameEvent>;
+
+#[derive(Deserialize, Serialize)]
+struct Envelope<M> {
+    version: u32,
+    message: M,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+pub fn encode<T: Serialize>(codec: Codec, message: &Message<T>) -> Vec<u8> {
+    codec.serialize(&Envelope {
+        version: PROTOCOL_VERSION,
+        message,
+    })
+}
+
+/// Decodes a message together with the protocol version of its sender. Only the handshake can
+/// be decoded if the versions differ.
+pub fn decode<T: DeserializeOwned>(
+    codec: Codec,
+    bytes: &[u8],
+) -> Result<(u32, Message<T>), ProtocolError> {
+    let version = codec.deserialize::<Header>(bytes)?.version;
+    match codec.deserialize::<Envelope<Message<T>>>(bytes) {
+        Ok(Envelope {
+            message: Message::Event(_),
+            ..
+        }) if version != PROTOCOL_VERSION => Err(ProtocolError::VersionMismatch(version)),
+        Ok(envelope) => Ok((version, envelope.message)),
+        Err(_) if version != PROTOCOL_VERSION => Err(ProtocolError::VersionMismatch(version)),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        events::ClientEvent,
+        protocol::{
+            decode, encode, ClientMessage, Codec, Message, ProtocolError, PROTOCOL_VERSION,
+        },
+    };
+
+    #[test]
+    fn decode_should_return_the_encoded_message() {
+        let message = Message::Event(ClientEvent::JoinRoom("table".to_string()));
+
+        for codec in [Codec::Json, Codec::Bincode] {
+            let decoded = decode::<ClientEvent>(codec, &encode(codec, &message)).unwrap();
+
+            assert_eq!(decoded, (PROTOCOL_VERSION, message.clone()));
+        }
+    }
+
+    #[test]
+    fn decode_should_only_read_the_handshake_of_other_versions() {
+        let hello = br#"{"version":999,"message":{"Hello":["Json"]}}"#;
+        let event = br#"{"version":999,"message":{"Event":"ListRooms"}}"#;
+        let unknown = br#"{"version":999,"message":{"Event":"Dance"}}"#;
+
+        assert_eq!(
+            decode::<ClientEvent>(Codec::Json, hello),
+            Ok((999, ClientMessage::Hello(vec![Codec::Json])))
+        );
+        assert_eq!(
+            decode::<ClientEvent>(Codec::Json, event),
+            Err(ProtocolError::VersionMismatch(999))
+        );
+        assert_eq!(
+            decode::<ClientEvent>(Codec::Json, unknown),
+            Err(ProtocolError::VersionMismatch(999))
+        );
+        assert!(matches!(
+            decode::<ClientEvent>(Codec::Json, b"garbage"),
+            Err(ProtocolError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn negotiate_should_prefer_bincode() {
+        assert_eq!(
+            Codec::negotiate(&[Codec::Json, Codec::Bincode]),
+            Codec::Bincode
+        );
+        assert_eq!(Codec::negotiate(&[Codec::Json]), Codec::Json);
+        assert_eq!(Codec::negotiate(&[]), Codec::Json);
+    }
+}
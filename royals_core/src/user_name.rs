@@ -1,27 +1,43 @@
+use crate::protocol::ProtocolError;
 use renet::transport::NETCODE_USER_DATA_BYTES;
 
+/// Usernames are sent as user data of the connect token, after their length
+pub const MAX_USERNAME_BYTES: usize = NETCODE_USER_DATA_BYTES - 8;
+
 pub struct Username(String);
 
 impl Username {
-    pub fn to_netcode_user_data(&self) -> [u8; NETCODE_USER_DATA_BYTES] {
+    pub fn to_netcode_user_data(&self) -> Result<[u8; NETCODE_USER_DATA_BYTES], ProtocolError> {
         let mut user_data = [0u8; NETCODE_USER_DATA_BYTES];
-        if self.0.len() > NETCODE_USER_DATA_BYTES - 8 {
-            panic!("Username is too big");
+        if self.0.len() > MAX_USERNAME_BYTES {
+            return Err(ProtocolError::InvalidUsername(format!(
+                "usernames have at most {} bytes",
+                MAX_USERNAME_BYTES
+            )));
         }
         user_data[0..8].copy_from_slice(&(self.0.len() as u64).to_le_bytes());
         user_data[8..self.0.len() + 8].copy_from_slice(self.0.as_bytes());
 
-        user_data
+        Ok(user_data)
     }
 
-    pub fn from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Self {
+    /// The user data comes from the client in unsecure mode, so it can be anything
+    pub fn from_user_data(
+        user_data: &[u8; NETCODE_USER_DATA_BYTES],
+    ) -> Result<Self, ProtocolError> {
         let mut buffer = [0u8; 8];
         buffer.copy_from_slice(&user_data[0..8]);
-        let mut len = u64::from_le_bytes(buffer) as usize;
-        len = len.min(NETCODE_USER_DATA_BYTES - 8);
-        let data = user_data[8..len + 8].to_vec();
-        let username = String::from_utf8(data).unwrap();
-        Self(username)
+        let len = u64::from_le_bytes(buffer);
+        if len > MAX_USERNAME_BYTES as u64 {
+            return Err(ProtocolError::InvalidUsername(format!(
+                "length {} exceeds {} bytes",
+                len, MAX_USERNAME_BYTES
+            )));
+        }
+        let data = user_data[8..len as usize + 8].to_vec();
+        let username =
+            String::from_utf8(data).map_err(|e| ProtocolError::InvalidUsername(e.to_string()))?;
+        Ok(Self(username))
     }
 
     pub fn to_str(&self) -> String {
@@ -32,3 +48,35 @@ impl Username {
         Username(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::user_name::{Username, MAX_USERNAME_BYTES};
+    use renet::transport::NETCODE_USER_DATA_BYTES;
+
+    #[test]
+    fn from_user_data_should_return_the_username() {
+        let user_data = Username::from_string("Foo".to_string())
+            .to_netcode_user_data()
+            .unwrap();
+
+        assert_eq!(
+            Username::from_user_data(&user_data).unwrap().to_str(),
+            "Foo"
+        );
+    }
+
+    #[test]
+    fn from_user_data_should_reject_invalid_data() {
+        let mut too_long = [0u8; NETCODE_USER_DATA_BYTES];
+        too_long[0..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut not_utf8 = [0xffu8; NETCODE_USER_DATA_BYTES];
+        not_utf8[0..8].copy_from_slice(&4u64.to_le_bytes());
+
+        assert!(Username::from_user_data(&too_long).is_err());
+        assert!(Username::from_user_data(&not_utf8).is_err());
+        assert!(Username::from_string("x".repeat(MAX_USERNAME_BYTES + 1))
+            .to_netcode_user_data()
+            .is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use itertools::Itertools;
+use renet::transport::{generate_random_bytes, ConnectToken, NETCODE_KEY_BYTES};
+use royals_core::user_name::Username;
+use std::{fs, io::Write, net::SocketAddr, path::Path, time::SystemTime};
+
+/// How long a connect token can be used to connect
+pub const TOKEN_EXPIRE_SECS: u64 = 24 * 60 * 60;
+/// Seconds without packets after which the connection times out
+const TOKEN_TIMEOUT_SECS: i32 = 15;
+
+pub type PrivateKey = [u8; NETCODE_KEY_BYTES];
+
+pub fn generate_private_key() -> PrivateKey {
+    generate_random_bytes()
+}
+
+/// The key is stored as hex digits so that it can be pasted into configs. Only the owner may
+/// read the file.
+pub fn save_private_key(path: &Path, key: &PrivateKey) -> Result<(), String> {
+    let hex = key.iter().map(|b| format!("{:02x}", b)).join("");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    // the mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))
+        .map_err(|e| e.to_string())?;
+    file.write_all((hex + "\n").as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+pub fn load_private_key(path: &Path) -> Result<PrivateKey, String> {
+    let hex = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_private_key(hex.trim())
+}
+
+fn parse_private_key(hex: &str) -> Result<PrivateKey, String> {
+    let invalid = || {
+        format!(
+            "A private key consists of {} hex digits",
+            2 * NETCODE_KEY_BYTES
+        )
+    };
+    if hex.len() != 2 * NETCODE_KEY_BYTES || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut key = [0u8; NETCODE_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+/// Issues a token signed with the private key of the server. The username is bound into the
+/// token, so the server can trust it.
+pub fn issue_connect_token(
+    private_key: &PrivateKey,
+    protocol_id: u64,
+    server_addr: SocketAddr,
+    username: &str,
+) -> Result<ConnectToken, String> {
+    let user_data = Username::from_string(username.to_string())
+        .to_netcode_user_data()
+        .map_err(|e| e.to_string())?;
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    ConnectToken::generate(
+        current_time,
+        protocol_id,
+        TOKEN_EXPIRE_SECS,
+        rand::random(),
+        TOKEN_TIMEOUT_SECS,
+        vec![server_addr],
+        Some(&user_data),
+        private_key,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{
+        generate_private_key, issue_connect_token, load_private_key, parse_private_key,
+        save_private_key,
+    };
+    use renet::transport::ConnectToken;
+
+    #[test]
+    fn parse_private_key_should_read_hex_digits() {
+        let hex = "00ff".repeat(16);
+
+        let key = parse_private_key(&hex).unwrap();
+
+        assert_eq!(key[0..2], [0x00, 0xff]);
+        assert!(parse_private_key("00ff").is_err());
+        assert!(parse_private_key(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn save_private_key_should_only_let_the_owner_read_it() {
+        let path = std::env::temp_dir().join(format!("royals_key_{}", std::process::id()));
+        let key = generate_private_key();
+
+        save_private_key(&path, &key).unwrap();
+
+        assert_eq!(load_private_key(&path), Ok(key));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn issue_connect_token_should_only_accept_usernames_that_fit() {
+        let key = generate_private_key();
+        let addr = "127.0.0.1:6969".parse().unwrap();
+
+        let token = issue_connect_token(&key, 0, addr, "Foo").unwrap();
+        let mut bytes = vec![];
+        token.write(&mut bytes).unwrap();
+
+        assert_eq!(
+            ConnectToken::read(&mut bytes.as_slice()).unwrap().client_id,
+            token.client_id
+        );
+        assert!(issue_connect_token(&key, 0, addr, &"x".repeat(300)).is_err());
+    }
+}
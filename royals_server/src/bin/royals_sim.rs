@@ -0,0 +1,137 @@
+use itertools::Itertools;
+use royals_server::{
+    card_counting_computer::CardCountingComputer,
+    game_lobby::GameLobby,
+    ismcts_computer::{IsmctsComputer, SearchBudget},
+    player::Player,
+    random_playing_computer::RandomPlayingComputer,
+    room::{MAX_SEATS, MIN_SEATS},
+    rule_set::RuleSet,
+    simulation::simulate,
+};
+use std::str::FromStr;
+
+const USAGE: &str = "Usage: royals_sim [GAMES] [THREADS] [SEED] [PLAYER_KIND]...";
+
+static PLAYER_KINDS: &[&str] = &["random", "counting", "ismcts[:ITERATIONS|:MILLISms]"];
+
+enum PlayerKind {
+    Random,
+    Counting,
+    /// The default budget unless one is given
+    Ismcts(Option<SearchBudget>),
+}
+
+fn parse_kind(kind: &str) -> Result<PlayerKind, String> {
+    match kind {
+        "random" => Ok(PlayerKind::Random),
+        "counting" => Ok(PlayerKind::Counting),
+        "ismcts" => Ok(PlayerKind::Ismcts(None)),
+        _ if kind.starts_with("ismcts:") => kind["ismcts:".len()..]
+            .parse()
+            .map(|budget| PlayerKind::Ismcts(Some(budget))),
+        _ => Err(format!(
+            "Unknown player kind {}, expected one of: {}",
+            kind,
+            PLAYER_KINDS.join(", ")
+        )),
+    }
+}
+
+fn seat_player(lobby: &mut GameLobby, kind: &PlayerKind, name: String) {
+    match *kind {
+        PlayerKind::Random => lobby.add_player(|| {
+            let mut player = RandomPlayingComputer::new();
+            player.data_mut().set_name(name);
+            player
+        }),
+        PlayerKind::Counting => lobby.add_player(|| {
+            let mut player = CardCountingComputer::new();
+            player.data_mut().set_name(name);
+            player
+        }),
+        PlayerKind::Ismcts(budget) => lobby.add_player(|| {
+            let mut player = budget.map_or_else(IsmctsComputer::new, IsmctsComputer::with_budget);
+            player.data_mut().set_name(name);
+            player
+        }),
+    }
+}
+
+fn parse_arg<T: FromStr>(args: &[String], index: usize, name: &str) -> Result<Option<T>, String> {
+    args.get(index)
+        .map(|s| s.parse().map_err(|_| format!("Invalid {} {}", name, s)))
+        .transpose()
+}
+
+fn exit_with(error: &str) -> ! {
+    println!("{}\n\n{}", error, USAGE);
+    std::process::exit(1)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        return println!("{}", USAGE);
+    }
+    let games: usize = parse_arg(&args, 1, "number of games")
+        .unwrap_or_else(|e| exit_with(&e))
+        .unwrap_or(100);
+    let threads: usize = parse_arg(&args, 2, "number of threads")
+        .unwrap_or_else(|e| exit_with(&e))
+        .unwrap_or(4);
+    let seed: u64 = parse_arg(&args, 3, "seed")
+        .unwrap_or_else(|e| exit_with(&e))
+        .unwrap_or_else(rand::random);
+    let mut kinds = args
+        .iter()
+        .skip(4)
+        .map(|kind| parse_kind(kind).map(|parsed| (kind.clone(), parsed)))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| exit_with(&e));
+    if kinds.is_empty() {
+        kinds = (0..4)
+            .map(|_| ("random".to_string(), PlayerKind::Random))
+            .collect();
+    }
+    if !(MIN_SEATS..=MAX_SEATS).contains(&kinds.len()) {
+        exit_with(&format!(
+            "A game has between {} and {} players, not {}",
+            MIN_SEATS,
+            MAX_SEATS,
+            kinds.len()
+        ));
+    }
+    let names = kinds
+        .iter()
+        .enumerate()
+        .map(|(i, (kind, _))| format!("{} {}", kind, i + 1))
+        .collect_vec();
+
+    let stats = simulate(games, threads, seed, &RuleSet::default(), |lobby| {
+        for ((_, kind), name) in kinds.iter().zip(&names) {
+            seat_player(lobby, kind, name.clone());
+        }
+    });
+
+    println!(
+        "Played {} games on {} threads starting with seed {}",
+        stats.games, threads, seed
+    );
+    println!("Win rates:");
+    for name in &names {
+        println!("  {}: {:.1}%", name, 100.0 * stats.win_rate(name));
+    }
+    println!(
+        "Average round length: {:.2} turns",
+        stats.average_round_length()
+    );
+    println!("Eliminations:");
+    for (reason, count) in stats
+        .eliminations
+        .iter()
+        .sorted_by_key(|&(_, count)| std::cmp::Reverse(*count))
+    {
+        println!("  {}: {}", reason, count);
+    }
+}
@@ -0,0 +1,66 @@
+use royals_core::protocol::PROTOCOL_ID;
+use royals_server::auth::{
+    generate_private_key, issue_connect_token, load_private_key, save_private_key,
+};
+use std::{fs::File, net::SocketAddr, path::Path};
+
+const USAGE: &str = "\
+Usage: royals_token keygen KEY_FILE
+       royals_token issue KEY_FILE SERVER_ADDR USERNAME TOKEN_FILE";
+
+fn keygen(key_file: &Path) -> Result<(), String> {
+    save_private_key(key_file, &generate_private_key())
+        .map_err(|e| format!("Could not write private key: {}", e))?;
+    println!("Private key written to {}", key_file.display());
+    Ok(())
+}
+
+fn issue(
+    key_file: &Path,
+    server_addr: SocketAddr,
+    username: &str,
+    token_file: &Path,
+) -> Result<(), String> {
+    load_private_key(key_file)
+        .and_then(|key| issue_connect_token(&key, PROTOCOL_ID, server_addr, username))
+        .and_then(|token| {
+            let mut file = File::create(token_file).map_err(|e| e.to_string())?;
+            token.write(&mut file).map_err(|e| e.to_string())
+        })
+        .map_err(|e| format!("Could not issue connect token: {}", e))?;
+    println!(
+        "Connect token for {} written to {}",
+        username,
+        token_file.display()
+    );
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, key_file] if command == "keygen" => keygen(Path::new(key_file)),
+        [command, key_file, server_addr, username, token_file] if command == "issue" => {
+            let server_addr = server_addr
+                .parse()
+                .map_err(|e| format!("Invalid server address {}: {}", server_addr, e))?;
+            issue(
+                Path::new(key_file),
+                server_addr,
+                username,
+                Path::new(token_file),
+            )
+        }
+        _ => Err("Unexpected arguments".to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        return println!("{}", USAGE);
+    }
+    if let Err(e) = run(&args) {
+        println!("{}\n\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
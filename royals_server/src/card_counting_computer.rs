@@ -0,0 +1,430 @@
+use crate::player::{Player, PlayerData};
+use itertools::Itertools;
+use royals_core::{
+    card::Card,
+    events::{Action, Event, Play, PlayerId},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COMPUTER_NAMES: &[&str] = &["Counter Delta", "Counter Echo", "Counter Foxtrot"];
+static COMPUTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Computer player which counts the cards seen in the log of the current round and picks the
+/// action with the highest expected value.
+pub struct CardCountingComputer {
+    pub data: PlayerData,
+}
+
+impl CardCountingComputer {
+    pub fn new() -> CardCountingComputer {
+        let my_id = COMPUTER_COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = COMPUTER_NAMES[my_id % COMPUTER_NAMES.len()].to_string();
+        CardCountingComputer {
+            data: PlayerData::new(name),
+        }
+    }
+}
+
+impl Default for CardCountingComputer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for CardCountingComputer {
+    fn data(&self) -> &PlayerData {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        &mut self.data
+    }
+
+    fn notify(&self, _game_log: &[Event], _players: &[&String]) {}
+
+    fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        let knowledge = Knowledge::from_log(players.len(), game_log);
+        valid_actions
+            .iter()
+            .map(|action| knowledge.score(action))
+            .position_max_by(|a, b| a.total_cmp(b))
+            .unwrap_or(0)
+    }
+}
+
+/// What can be inferred about the current round from the filtered log of a player.
+pub struct Knowledge {
+    pub me: PlayerId,
+    pub hand: Vec<Card>,
+    /// Cards which have not been seen yet, neither played, folded nor held by this player
+    pub unseen: HashMap<Card, usize>,
+    /// Hand cards of opponents learned through the Priest, Baroness or Cardinal
+    pub known: HashMap<PlayerId, Card>,
+    pub protected: HashSet<PlayerId>,
+    pub dropped: HashSet<PlayerId>,
+    pub discarded: HashMap<PlayerId, Vec<Card>>,
+    /// Number of cards left in the deck
+    pub remaining: usize,
+    pub sycophant_target: Option<PlayerId>,
+    pub jester_bets: Vec<(PlayerId, PlayerId)>,
+}
+
+impl Knowledge {
+    pub fn from_log(player_count: usize, game_log: &[Event]) -> Self {
+        let round_start = game_log
+            .iter()
+            .rposition(|e| matches!(e, Event::RoundStarted(..)))
+            .unwrap_or(0);
+        let round_log = &game_log[round_start..];
+
+        // the current player always has picked up the last card
+        let me = round_log
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                Event::PickUp(p, _, _) => Some(*p),
+                _ => None,
+            })
+            .unwrap_or(0);
+
+        // the own cards are revealed to the player, also the ones received in exchange
+        let mut hand: Vec<Card> = vec![];
+        for event in round_log {
+            match event {
+                Event::PickUp(p, Some(card), _) if *p == me => hand.push(*card),
+                Event::Play(p, Play { card, .. }) | Event::Fold(p, card, _) if *p == me => {
+                    if let Some(i) = hand.iter().position(|c| c == card) {
+                        hand.remove(i);
+                    }
+                }
+                Event::LearnedCard(p, Some(card)) if *p == me => hand = vec![*card],
+                _ => {}
+            }
+        }
+
+        let mut unseen: HashMap<Card, usize> = HashMap::new();
+        for card in Card::deck_for(player_count) {
+            *unseen.entry(card).or_default() += 1;
+        }
+        let mut remove_seen = |card: Card| {
+            if let Some(count) = unseen.get_mut(&card) {
+                *count = count.saturating_sub(1);
+            }
+        };
+        for &card in &hand {
+            remove_seen(card);
+        }
+
+        let mut known: HashMap<PlayerId, Card> = HashMap::new();
+        let mut protected: HashSet<PlayerId> = HashSet::new();
+        let mut dropped: HashSet<PlayerId> = HashSet::new();
+        let mut discarded: HashMap<PlayerId, Vec<Card>> = HashMap::new();
+        let mut remaining = 0;
+        let mut sycophant_target = None;
+        let mut jester_bets = vec![];
+        for event in round_log {
+            match event {
+                Event::Play(p, play) => {
+                    remove_seen(play.card);
+                    discarded.entry(*p).or_default().push(play.card);
+                    sycophant_target = match play.card {
+                        Card::Sycophant => play.opponent,
+                        _ => None,
+                    };
+                    if let (Card::Jester, Some(op)) = (play.card, play.opponent) {
+                        jester_bets.push((*p, op));
+                    }
+                    protected.remove(p);
+                    if play.card == Card::Maid {
+                        protected.insert(*p);
+                    }
+                    if known.get(p) == Some(&play.card) {
+                        known.remove(p);
+                    }
+                    if matches!(play.card, Card::King | Card::Cardinal) {
+                        for op in play.opponent.iter().chain(play.second_opponent.iter()) {
+                            known.remove(op);
+                        }
+                        known.remove(p);
+                    }
+                }
+                Event::Fold(p, card, _) => {
+                    remove_seen(*card);
+                    discarded.entry(*p).or_default().push(*card);
+                    known.remove(p);
+                }
+                Event::PickUp(_, _, left) => remaining = *left,
+                Event::DropOut(p) => {
+                    dropped.insert(*p);
+                }
+                Event::BurnFaceUp(card) => remove_seen(*card),
+                Event::LearnedCard(p, Some(card)) if *p != me => {
+                    known.insert(*p, *card);
+                }
+                _ => {}
+            }
+        }
+        // the hands of other players are accounted for separately
+        for card in known.values() {
+            remove_seen(*card);
+        }
+
+        Knowledge {
+            me,
+            hand,
+            unseen,
+            known,
+            protected,
+            dropped,
+            discarded,
+            remaining,
+            sycophant_target,
+            jester_bets,
+        }
+    }
+
+    /// Probability of each card value being the hand card of the given opponent
+    pub fn value_distribution(&self, opponent: PlayerId) -> HashMap<u8, f64> {
+        let mut distribution: HashMap<u8, f64> = HashMap::new();
+        if let Some(card) = self.known.get(&opponent) {
+            distribution.insert(card.value(), 1.0);
+            return distribution;
+        }
+        let total = self.unseen.values().sum::<usize>().max(1) as f64;
+        for (card, &count) in &self.unseen {
+            *distribution.entry(card.value()).or_default() += count as f64 / total;
+        }
+        distribution
+    }
+
+    fn kept_card(&self, played: Card) -> Card {
+        let mut hand = self.hand.clone();
+        if let Some(i) = hand.iter().position(|&c| c == played) {
+            hand.remove(i);
+        }
+        hand.first().copied().unwrap_or(played)
+    }
+
+    fn probability(&self, opponent: PlayerId, predicate: impl Fn(u8) -> bool) -> f64 {
+        self.value_distribution(opponent)
+            .iter()
+            .filter(|&(&value, _)| predicate(value))
+            .map(|(_, p)| p)
+            .sum()
+    }
+
+    /// Expected value of an action, higher is better
+    pub fn score(&self, action: &Action) -> f64 {
+        let play = match action {
+            Action::GiveUp => return -100.0,
+            Action::Play(play) => play,
+        };
+        if play
+            .opponent
+            .iter()
+            .chain(play.second_opponent.iter())
+            .any(|op| self.protected.contains(op))
+        {
+            // attacking a protected player lets us drop out
+            return -100.0;
+        }
+        let kept = self.kept_card(play.card);
+        // prefer keeping high cards for the end of the round
+        let keep_bonus = 0.05 * kept.value() as f64;
+        keep_bonus + self.effect_score(play, kept)
+    }
+
+    fn effect_score(&self, play: &Play, kept: Card) -> f64 {
+        let Some(op) = play.opponent else {
+            return match play.card {
+                Card::Princess => -100.0,
+                Card::Maid => 0.3,
+                _ => 0.0,
+            };
+        };
+        let mine = kept.value();
+        match play.card {
+            Card::Guard => match play.guess {
+                Some(guess) => {
+                    if self.known.get(&op) == Some(&Card::Assassin) {
+                        return -100.0;
+                    }
+                    self.probability(op, |v| v == guess.value())
+                }
+                None => 0.0,
+            },
+            Card::Priest | Card::Baroness => {
+                if self.known.contains_key(&op) {
+                    0.0
+                } else {
+                    0.2
+                }
+            }
+            Card::Baron => self.probability(op, |v| v < mine) - self.probability(op, |v| v > mine),
+            Card::DowagerQueen => {
+                self.probability(op, |v| v > mine) - self.probability(op, |v| v < mine)
+            }
+            Card::Prince => {
+                let princess = self.probability(op, |v| v == Card::Princess.value());
+                let expected = self.probability(op, |v| v >= 5);
+                princess + 0.1 * expected
+            }
+            Card::King => {
+                let better = self.probability(op, |v| v > mine);
+                0.5 * (better - self.probability(op, |v| v < mine))
+            }
+            Card::Bishop => match play.guess {
+                Some(guess) => 0.8 * self.probability(op, |v| v == guess.value()),
+                None => 0.0,
+            },
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        card_counting_computer::{CardCountingComputer, Knowledge},
+        player::Player,
+    };
+    use royals_core::{
+        card::Card,
+        events::{Action, Event, Play},
+    };
+
+    fn guard(opponent: usize, guess: Card) -> Action {
+        Action::Play(Play {
+            card: Card::Guard,
+            opponent: Some(opponent),
+            second_opponent: None,
+            guess: Some(guess),
+        })
+    }
+
+    #[test]
+    fn from_log_should_know_the_own_hand() {
+        let game_log = vec![
+            Event::RoundStarted(1, 0, 0),
+            Event::PickUp(0, Some(Card::Princess), 10),
+            Event::PickUp(1, None, 9),
+            Event::PickUp(0, Some(Card::Guard), 8),
+        ];
+
+        assert_eq!(
+            Knowledge::from_log(2, &game_log).hand,
+            [Card::Princess, Card::Guard]
+        );
+
+        let game_log = [
+            &game_log[..],
+            &[
+                Event::Play(
+                    0,
+                    Play {
+                        card: Card::Guard,
+                        opponent: Some(1),
+                        second_opponent: None,
+                        guess: Some(Card::Priest),
+                    },
+                ),
+                Event::PickUp(1, None, 7),
+                Event::Play(
+                    1,
+                    Play {
+                        card: Card::King,
+                        opponent: Some(0),
+                        second_opponent: None,
+                        guess: None,
+                    },
+                ),
+                Event::LearnedCard(0, Some(Card::Maid)),
+                Event::PickUp(0, Some(Card::Countess), 6),
+            ],
+        ]
+        .concat();
+
+        assert_eq!(
+            Knowledge::from_log(2, &game_log).hand,
+            [Card::Maid, Card::Countess]
+        );
+    }
+
+    #[test]
+    fn obtain_action_should_guess_learned_card() {
+        let computer = CardCountingComputer::new();
+        let names = ["A".to_string(), "B".to_string()];
+        let players = names.iter().collect::<Vec<_>>();
+        let game_log = vec![
+            Event::RoundStarted(1, 0, 0),
+            Event::PickUp(0, Some(Card::Priest), 10),
+            Event::PickUp(1, None, 9),
+            Event::PickUp(0, Some(Card::Guard), 8),
+            Event::Play(
+                0,
+                Play {
+                    card: Card::Priest,
+                    opponent: Some(1),
+                    second_opponent: None,
+                    guess: None,
+                },
+            ),
+            Event::LearnedCard(1, Some(Card::Baron)),
+            Event::PickUp(1, None, 7),
+            Event::Play(
+                1,
+                Play {
+                    card: Card::Guard,
+                    opponent: Some(0),
+                    second_opponent: None,
+                    guess: Some(Card::King),
+                },
+            ),
+            Event::PickUp(0, Some(Card::Guard), 6),
+        ];
+        let actions = vec![
+            Action::GiveUp,
+            guard(1, Card::Priest),
+            guard(1, Card::Baron),
+            guard(1, Card::Princess),
+        ];
+
+        let chosen = computer.obtain_action(&players, &game_log, &actions);
+
+        assert_eq!(actions[chosen], guard(1, Card::Baron));
+    }
+
+    #[test]
+    fn obtain_action_should_not_attack_protected_players() {
+        let computer = CardCountingComputer::new();
+        let names = ["A".to_string(), "B".to_string(), "C".to_string()];
+        let players = names.iter().collect::<Vec<_>>();
+        let game_log = vec![
+            Event::RoundStarted(1, 1, 0),
+            Event::PickUp(1, None, 10),
+            Event::Play(
+                1,
+                Play {
+                    card: Card::Maid,
+                    opponent: None,
+                    second_opponent: None,
+                    guess: None,
+                },
+            ),
+            Event::PickUp(2, Some(Card::Guard), 9),
+        ];
+        let actions = vec![guard(1, Card::Princess), guard(0, Card::Priest)];
+
+        let chosen = computer.obtain_action(&players, &game_log, &actions);
+
+        assert_eq!(chosen, 1);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{room::BotLimits, rule_set::RuleSet};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+pub const USAGE: &str = "\
+Usage: royals_server [OPTIONS]
+       royals_server replay GAME_RECORD
+
+Options override the settings of the config file:
+  -c, --config FILE           TOML config file, see server.toml
+  -b, --bind ADDRESS          address to listen on
+  -p, --port PORT             port to listen on
+      --public-address ADDR   address the clients connect to, if it differs
+      --max-clients N         connections served at once
+      --tick-millis MILLIS    time between two updates of the network loop
+      --seed SEED             seed of the first game, random if not set
+      --log-dir DIR           directory the game records are written to
+      --key-file FILE         only let clients with a token signed with this key in
+  -h, --help                  print this help";
+
+/// Who may connect to the server
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AuthMode {
+    /// Anybody, with the name they chose
+    #[default]
+    Unsecure,
+    /// Clients with a connect token signed with the private key, see royals_token
+    Secure { key_file: PathBuf },
+}
+
+/// Settings of the server, read from a TOML file. Missing settings keep their default.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    /// Address the clients connect to if they can not reach the bound one, e.g. behind NAT
+    pub public_address: Option<SocketAddr>,
+    pub max_clients: usize,
+    pub tick_millis: u64,
+    /// Seed of the first game, the following games count up from it
+    pub seed: Option<u64>,
+    /// Directory the records of the games are written to
+    pub log_dir: PathBuf,
+    /// House rules of all games
+    pub rules: RuleSet,
+    pub bots: BotLimits,
+    pub auth: AuthMode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 6969,
+            public_address: None,
+            max_clients: 64,
+            tick_millis: 50,
+            seed: None,
+            log_dir: PathBuf::from("game_records"),
+            rules: RuleSet::default(),
+            bots: BotLimits::default(),
+            auth: AuthMode::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    Argument(String),
+    File(PathBuf, String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Argument(e) => write!(f, "Invalid argument: {}", e),
+            ConfigError::File(path, e) => {
+                write!(f, "Can not read config file {}: {}", path.display(), e)
+            }
+            ConfigError::Invalid(e) => write!(f, "Invalid configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text =
+            fs::read_to_string(path).map_err(|e| ConfigError::File(path.into(), e.to_string()))?;
+        toml::from_str(&text).map_err(|e| ConfigError::File(path.into(), e.to_string()))
+    }
+
+    pub fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn public_addr(&self) -> SocketAddr {
+        self.public_address.unwrap_or(self.bind_addr())
+    }
+
+    fn validate(self) -> Result<Config, ConfigError> {
+        if self.max_clients == 0 {
+            return Err(ConfigError::Invalid(
+                "at least one client has to be served".to_string(),
+            ));
+        }
+        if self.tick_millis == 0 {
+            return Err(ConfigError::Invalid(
+                "the tick has to last at least a millisecond".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// What the server binary was asked to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Serve(Config),
+    Replay(PathBuf),
+    Help,
+}
+
+impl Command {
+    /// Parses the arguments without the name of the binary. The options are applied on top of
+    /// the config file, wherever it is given.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ConfigError> {
+        let args: Vec<String> = args.into_iter().collect();
+        if args.first().map(|s| s.as_str()) == Some("replay") {
+            return match &args[1..] {
+                [record] => Ok(Command::Replay(PathBuf::from(record))),
+                _ => Err(ConfigError::Argument(
+                    "replay takes exactly one game record".to_string(),
+                )),
+            };
+        }
+
+        let mut options = vec![];
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            if flag == "-h" || flag == "--help" {
+                return Ok(Command::Help);
+            }
+            if !flag.starts_with('-') {
+                return Err(ConfigError::Argument(format!("unexpected {}", flag)));
+            }
+            let value = args
+                .next()
+                .ok_or(ConfigError::Argument(format!("{} needs a value", flag)))?;
+            options.push((flag, value));
+        }
+
+        let mut config = match options
+            .iter()
+            .find(|(flag, _)| flag == "-c" || flag == "--config")
+        {
+            Some((_, path)) => Config::load(Path::new(path))?,
+            None => Config::default(),
+        };
+        for (flag, value) in options {
+            match flag.as_str() {
+                "-c" | "--config" => {}
+                "-b" | "--bind" => config.bind_address = parse(&flag, &value)?,
+                "-p" | "--port" => config.port = parse(&flag, &value)?,
+                "--public-address" => config.public_address = Some(parse(&flag, &value)?),
+                "--max-clients" => config.max_clients = parse(&flag, &value)?,
+                "--tick-millis" => config.tick_millis = parse(&flag, &value)?,
+                "--seed" => config.seed = Some(parse(&flag, &value)?),
+                "--log-dir" => config.log_dir = PathBuf::from(value),
+                "--key-file" => {
+                    config.auth = AuthMode::Secure {
+                        key_file: PathBuf::from(value),
+                    }
+                }
+                _ => return Err(ConfigError::Argument(format!("unknown option {}", flag))),
+            }
+        }
+        config.validate().map(Command::Serve)
+    }
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| ConfigError::Argument(format!("{} {}: {}", flag, value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{AuthMode, Command, Config, ConfigError},
+        room::BotLimits,
+    };
+    use royals_core::events::BotLevel;
+    use std::path::PathBuf;
+
+    #[test]
+    fn example_config_should_match_the_defaults() {
+        let config: Config = toml::from_str(include_str!("../server.toml")).unwrap();
+
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn config_should_keep_the_defaults_of_missing_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            port = 7000
+
+            [rules]
+            princess_playable = false
+
+            [bots]
+            max_level = "Easy"
+
+            [auth]
+            mode = "secure"
+            key_file = "server.key"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.max_clients, 64);
+        assert!(!config.rules.princess_playable);
+        assert!(config.rules.give_up_allowed);
+        assert_eq!(
+            config.bots,
+            BotLimits {
+                max_level: BotLevel::Easy,
+                ..BotLimits::default()
+            }
+        );
+        assert_eq!(
+            config.auth,
+            AuthMode::Secure {
+                key_file: PathBuf::from("server.key")
+            }
+        );
+        assert!(toml::from_str::<Config>("prot = 7000").is_err());
+    }
+
+    #[test]
+    fn from_args_should_override_the_defaults() {
+        let command = Command::from_args(args(&[
+            "--port",
+            "7000",
+            "-b",
+            "127.0.0.1",
+            "--seed",
+            "42",
+            "--key-file",
+            "server.key",
+        ]))
+        .unwrap();
+
+        let Command::Serve(config) = command else {
+            panic!("expected to serve, got {:?}", command);
+        };
+        assert_eq!(config.bind_addr(), "127.0.0.1:7000".parse().unwrap());
+        assert_eq!(config.public_addr(), config.bind_addr());
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(
+            config.auth,
+            AuthMode::Secure {
+                key_file: PathBuf::from("server.key")
+            }
+        );
+    }
+
+    #[test]
+    fn from_args_should_reject_invalid_arguments() {
+        assert!(matches!(
+            Command::from_args(args(&["6969"])),
+            Err(ConfigError::Argument(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["--port", "high"])),
+            Err(ConfigError::Argument(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["--tick-millis", "0"])),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["--config", "does/not/exist.toml"])),
+            Err(ConfigError::File(..))
+        ));
+        assert!(Command::from_args(args(&["replay"])).is_err());
+    }
+
+    #[test]
+    fn from_args_should_read_the_subcommands() {
+        assert_eq!(
+            Command::from_args(args(&["replay", "game.json"])),
+            Ok(Command::Replay(PathBuf::from("game.json")))
+        );
+        assert_eq!(
+            Command::from_args(args(&["--port", "7000", "--help"])),
+            Ok(Command::Help)
+        );
+    }
+
+    // Infra ----
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+}
@@ -1,43 +1,277 @@
-use crate::{game_logic::GameState, player::Player};
+use crate::{
+    game_logic::GameState,
+    game_record::{GameRecord, RoundRecord, SwapRecord},
+    player::AsyncPlayer,
+    rule_set::RuleSet,
+};
+use itertools::Itertools;
 use royals_core::{
     card::Card,
-    events::{ActionId, EventEntry},
+    events::{ActionId, Event, EventEntry, EventVisibility, PlayerId},
 };
 
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum SwapReason {
+    /// A computer player takes over for the rest of the round
+    Takeover,
+    /// The player comes back at the start of the next round
+    Return,
+}
+
+impl SwapReason {
+    /// Announcement of the swap at the given seat
+    pub fn event(self, id: PlayerId) -> Event {
+        match self {
+            SwapReason::Takeover => Event::SeatTakenOver(id),
+            SwapReason::Return => Event::SeatReturned(id),
+        }
+    }
+}
+
+/// Replacement of the player at the seat with the given name
+pub struct SeatSwap {
+    pub name: String,
+    pub player: Box<dyn AsyncPlayer>,
+    pub reason: SwapReason,
+}
+
+/// The match as seen by spectators after every action
+pub struct SpectatorUpdate {
+    pub players: Vec<String>,
+    /// Only what happened in the open
+    pub public_log: Vec<Event>,
+    /// Every card revealed
+    pub full_log: Vec<Event>,
+}
 
 pub struct GameLobby {
-    players: Vec<Box<dyn Player>>,
+    players: Vec<Box<dyn AsyncPlayer>>,
+    rules: RuleSet,
+    seed: u64,
+    rng: ChaCha8Rng,
+    record: GameRecord,
+    record_path: Option<PathBuf>,
+    seat_swaps: Option<UnboundedReceiver<SeatSwap>>,
+    pending_swaps: Vec<SeatSwap>,
+    spectators: Option<UnboundedSender<SpectatorUpdate>>,
+    /// Log of the last match played, hidden events included
+    game_log: Vec<EventEntry>,
 }
 
 impl GameLobby {
-    pub fn new() -> Self {
-        GameLobby { players: vec![] }
+    /// The seed determines the seating order and the seeds of all rounds of the match.
+    pub fn new(rules: RuleSet, seed: u64) -> Self {
+        GameLobby {
+            players: vec![],
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            record: GameRecord::new(seed, rules.clone(), vec![]),
+            rules,
+            record_path: None,
+            seat_swaps: None,
+            pending_swaps: vec![],
+            spectators: None,
+            game_log: vec![],
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The record of the match is written to the given file after every round.
+    pub fn record_to(&mut self, path: PathBuf) {
+        self.record_path = Some(path);
+    }
+
+    pub fn record(&self) -> &GameRecord {
+        &self.record
+    }
+
+    pub fn game_log(&self) -> &[EventEntry] {
+        &self.game_log
     }
 
     pub fn add_player<C, T>(&mut self, player_constructor: C)
     where
         C: FnOnce() -> T,
-        T: Player + 'static,
+        T: AsyncPlayer + 'static,
     {
         let player = player_constructor();
         self.players.push(Box::new(player));
     }
 
+    /// Seats are swapped as requested through the receiver while the match is running.
+    pub fn swap_seats_from(&mut self, receiver: UnboundedReceiver<SeatSwap>) {
+        self.seat_swaps = Some(receiver);
+    }
+
+    /// Every notification of the players is followed by an update for the spectators.
+    pub fn broadcast_to(&mut self, spectators: UnboundedSender<SpectatorUpdate>) {
+        self.spectators = Some(spectators);
+    }
+
+    /// Replaces the player at a seat, the new one keeps the id and the name of the seat
+    pub fn replace_player(
+        &mut self,
+        id: PlayerId,
+        mut player: Box<dyn AsyncPlayer>,
+    ) -> Box<dyn AsyncPlayer> {
+        player.data_mut().set_name(self.players[id].name().clone());
+        std::mem::replace(&mut self.players[id], player)
+    }
+
+    /// Applies the swaps received so far and returns the swapped seats. Returning players have
+    /// to wait for the start of the next round.
+    async fn apply_seat_swaps(
+        &mut self,
+        round_start: bool,
+        game_log: &mut Vec<EventEntry>,
+    ) -> Vec<PlayerId> {
+        if let Some(receiver) = &mut self.seat_swaps {
+            while let Ok(swap) = receiver.try_recv() {
+                self.pending_swaps.push(swap);
+            }
+        }
+        let (swaps, pending) = std::mem::take(&mut self.pending_swaps)
+            .into_iter()
+            .partition(|swap| round_start || matches!(swap.reason, SwapReason::Takeover));
+        self.pending_swaps = pending;
+
+        let mut swapped = vec![];
+        for swap in swaps {
+            let Some(id) = self.players.iter().position(|p| *p.name() == swap.name) else {
+                continue;
+            };
+            self.replace_player(id, swap.player);
+            if let Some(round) = self.record.rounds.last_mut() {
+                round.swaps.push(SwapRecord {
+                    action: round.actions.len(),
+                    seat: id,
+                    reason: swap.reason,
+                });
+            }
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: swap.reason.event(id),
+            });
+            // the new player catches up with the round
+            self.players[id]
+                .notify(
+                    &GameState::filter_event(game_log, Some(id)),
+                    &self.player_names(),
+                )
+                .await;
+            swapped.push(id);
+        }
+        swapped
+    }
+
     pub fn player_names(&self) -> Vec<&String> {
         self.players.iter().map(|p| p.name()).collect::<Vec<_>>()
     }
 
-    pub fn play_round(&mut self) {
+    pub fn tokens_to_win(player_count: usize) -> usize {
+        match player_count {
+            0..=2 => 7,
+            3 => 5,
+            4 => 4,
+            _ => 3,
+        }
+    }
+
+    /// Plays the match on the current thread, computer players which think on the blocking
+    /// thread pool of tokio included.
+    pub fn play_match(&mut self) -> Vec<PlayerId> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("a runtime without I/O can always be built")
+            .block_on(self.run_match())
+    }
+
+    /// Plays the match, awaiting the players whenever they have to decide
+    pub async fn run_match(&mut self) -> Vec<PlayerId> {
         let mut game_log: Vec<EventEntry> = vec![];
 
-        let mut deck_to_shuffle = Card::deck();
-        deck_to_shuffle.shuffle(&mut rand::thread_rng());
-        let deck = deck_to_shuffle;
+        self.players.shuffle(&mut self.rng);
+        self.record = GameRecord::new(
+            self.seed,
+            self.rules.clone(),
+            self.player_names().into_iter().cloned().collect(),
+        );
+        game_log.push(EventEntry {
+            visibility: EventVisibility::Public,
+            event: Event::MatchStarted(self.seed),
+        });
+
+        let tokens_to_win = Self::tokens_to_win(self.players.len());
+        let mut tokens = vec![0; self.players.len()];
+        let mut starting_player = 0;
+        let mut round = 0;
+
+        loop {
+            round += 1;
+            let round_seed = self.rng.gen();
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: Event::RoundStarted(round, starting_player, round_seed),
+            });
+
+            let (round_winners, round_tokens) = self
+                .play_round(starting_player, round_seed, &mut game_log)
+                .await;
+
+            self.save_record();
 
-        self.players.shuffle(&mut rand::thread_rng());
+            let match_winners =
+                Self::award_tokens(&mut tokens, tokens_to_win, round_tokens, &mut game_log);
+            self.notify_players(&game_log).await;
+            if !match_winners.is_empty() {
+                self.game_log = game_log;
+                return match_winners;
+            }
+
+            // the winner of the previous round starts the next one
+            if let Some(&winner) = round_winners.first() {
+                starting_player = winner;
+            }
+        }
+    }
+
+    /// Plays a single round and returns its winners and the receivers of tokens
+    pub async fn play_round(
+        &mut self,
+        starting_player: PlayerId,
+        seed: u64,
+        game_log: &mut Vec<EventEntry>,
+    ) -> (Vec<PlayerId>, Vec<PlayerId>) {
+        let mut deck_to_shuffle = Card::deck_for(self.players.len());
+        deck_to_shuffle.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+        let deck = deck_to_shuffle;
+        self.record.rounds.push(RoundRecord {
+            seed,
+            starting_player,
+            deck: deck.clone(),
+            actions: vec![],
+            timeouts: vec![],
+            swaps: vec![],
+        });
 
-        let mut state = GameState::new(self.players.len(), &deck, &mut game_log);
+        let mut state = GameState::new(
+            self.players.len(),
+            starting_player,
+            deck,
+            self.rules.clone(),
+            game_log,
+        );
+        self.apply_seat_swaps(true, game_log).await;
 
         loop {
             let (players_turn, actions) = state.valid_actions();
@@ -46,31 +280,126 @@ impl GameLobby {
                 break;
             }
 
-            let chosen_action: ActionId = self.players[players_turn.unwrap()].obtain_action(
-                &self.player_names(),
-                &GameState::filter_event(&game_log, players_turn),
-                &actions,
-            );
+            let chosen_action: ActionId = self.players[players_turn.unwrap()]
+                .obtain_action(
+                    &self.player_names(),
+                    &GameState::filter_event(game_log, players_turn),
+                    &actions,
+                )
+                .await;
 
-            state.handle_action(chosen_action, &mut game_log);
+            // the player may have left during their turn, the new one decides instead
+            if self
+                .apply_seat_swaps(false, game_log)
+                .await
+                .contains(&players_turn.unwrap())
+            {
+                continue;
+            }
 
-            for (i, p) in self.players.iter().enumerate() {
-                p.notify(
-                    &GameState::filter_event(&game_log, Some(i)),
-                    &self.player_names(),
+            // the player is asked again until the action is one of the valid ones
+            let Some(action) = actions.get(chosen_action) else {
+                println!(
+                    "{} chose action {} out of {}",
+                    self.players[players_turn.unwrap()].name(),
+                    chosen_action,
+                    actions.len()
                 );
+                continue;
+            };
+
+            let timed_out = self.players[players_turn.unwrap()].timed_out();
+            if timed_out {
+                game_log.push(EventEntry {
+                    visibility: EventVisibility::Public,
+                    event: Event::TurnTimedOut(players_turn.unwrap()),
+                });
+            }
+            if let Some(round) = self.record.rounds.last_mut() {
+                if timed_out {
+                    round.timeouts.push(round.actions.len());
+                }
+                round.actions.push(action.clone());
+            }
+            // checked above already
+            _ = state.handle_action(chosen_action, game_log);
+
+            self.notify_players(game_log).await;
+        }
+
+        (state.round_winners(), state.round_tokens())
+    }
+
+    /// Awards the tokens of a round and returns the match winners once they have enough tokens
+    pub fn award_tokens(
+        tokens: &mut [usize],
+        tokens_to_win: usize,
+        round_tokens: Vec<PlayerId>,
+        game_log: &mut Vec<EventEntry>,
+    ) -> Vec<PlayerId> {
+        for player in round_tokens {
+            tokens[player] += 1;
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: Event::TokenAwarded(player, tokens[player]),
+            });
+        }
+
+        let match_winners = tokens
+            .iter()
+            .positions(|&t| t >= tokens_to_win)
+            .collect_vec();
+        if !match_winners.is_empty() {
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: Event::MatchWinner(match_winners.clone()),
+            });
+        }
+        match_winners
+    }
+
+    fn save_record(&self) {
+        if let Some(path) = &self.record_path {
+            if let Err(e) = self.record.save(path) {
+                println!("Could not write game record to {}: {}", path.display(), e);
             }
         }
     }
+
+    async fn notify_players(&self, game_log: &[EventEntry]) {
+        for (i, p) in self.players.iter().enumerate() {
+            p.notify(
+                &GameState::filter_event(game_log, Some(i)),
+                &self.player_names(),
+            )
+            .await;
+        }
+        if let Some(spectators) = &self.spectators {
+            // the match goes on when nobody watches anymore
+            _ = spectators.send(SpectatorUpdate {
+                players: self.player_names().into_iter().cloned().collect(),
+                public_log: GameState::public_events(game_log),
+                full_log: GameState::filter_event(game_log, None),
+            });
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        game_lobby::GameLobby,
-        player::{Player, PlayerData},
+        game_lobby::{GameLobby, SeatSwap, SwapReason},
+        game_logic::GameState,
+        game_record::GameRecord,
+        player::{BlockingPlayer, Player, PlayerData},
+        random_playing_computer::RandomPlayingComputer,
+        rule_set::RuleSet,
     };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
     use royals_core::events::{Action, Event};
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::mpsc::unbounded_channel;
 
     #[test]
     fn player_names_should_return_list_of_names() {
@@ -79,21 +408,189 @@ mod tests {
                 Box::new(TestPlayer::new("Foo")),
                 Box::new(TestPlayer::new("Bar")),
             ],
+            rules: RuleSet::default(),
+            seed: 0,
+            rng: ChaCha8Rng::seed_from_u64(0),
+            record: GameRecord::new(0, RuleSet::default(), vec![]),
+            record_path: None,
+            seat_swaps: None,
+            pending_swaps: vec![],
+            spectators: None,
+            game_log: vec![],
         };
 
         assert_eq!(lobby.player_names(), vec!["Foo", "Bar"]);
     }
 
+    #[test]
+    fn tokens_to_win_should_depend_on_player_count() {
+        assert_eq!(GameLobby::tokens_to_win(2), 7);
+        assert_eq!(GameLobby::tokens_to_win(3), 5);
+        assert_eq!(GameLobby::tokens_to_win(4), 4);
+        assert_eq!(GameLobby::tokens_to_win(8), 3);
+    }
+
+    #[test]
+    fn play_match_should_finish_once_a_player_has_enough_tokens() {
+        let mut lobby = GameLobby::new(RuleSet::default(), 42);
+        lobby.add_player(|| TestPlayer::new("Foo"));
+        lobby.add_player(|| TestPlayer::new("Bar"));
+
+        let winners = lobby.play_match();
+
+        assert!(!winners.is_empty());
+    }
+
+    #[test]
+    fn play_match_should_support_up_to_eight_players() {
+        let mut lobby = GameLobby::new(RuleSet::default(), 42);
+        for name in ["A", "B", "C", "D", "E", "F", "G", "H"] {
+            lobby.add_player(|| TestPlayer::new(name));
+        }
+
+        let winners = lobby.play_match();
+
+        assert!(!winners.is_empty());
+    }
+
+    #[tokio::test]
+    async fn run_match_should_let_computers_think_on_blocking_threads() {
+        let mut lobby = GameLobby::new(RuleSet::default(), 42);
+        lobby.add_player(|| BlockingPlayer::new(TestPlayer::new("Foo")));
+        lobby.add_player(|| BlockingPlayer::new(TestPlayer::new("Bar")));
+
+        let winners = lobby.run_match().await;
+
+        assert!(!winners.is_empty());
+        assert_eq!(lobby.player_names().len(), 2);
+    }
+
+    #[test]
+    fn play_match_should_announce_seats_taken_over() {
+        let observed_log = Arc::new(Mutex::new(vec![]));
+        let mut lobby = GameLobby::new(RuleSet::default(), 42);
+        lobby.add_player(|| TestPlayer::observing("Foo", observed_log.clone()));
+        lobby.add_player(|| TestPlayer::new("Bar"));
+        let (swap_tx, swap_rx) = unbounded_channel();
+        lobby.swap_seats_from(swap_rx);
+        swap_tx
+            .send(SeatSwap {
+                name: "Bar".to_string(),
+                player: Box::new(RandomPlayingComputer::new()),
+                reason: SwapReason::Takeover,
+            })
+            .unwrap();
+
+        lobby.play_match();
+
+        let bar = lobby
+            .player_names()
+            .iter()
+            .position(|&n| n == "Bar")
+            .unwrap();
+        assert!(observed_log
+            .lock()
+            .unwrap()
+            .contains(&Event::SeatTakenOver(bar)));
+    }
+
+    #[test]
+    fn spectators_should_only_see_public_cards() {
+        let mut lobby = GameLobby::new(RuleSet::default(), 5);
+        lobby.add_player(|| TestPlayer::new("Foo"));
+        lobby.add_player(|| TestPlayer::new("Bar"));
+        let (spectator_tx, mut spectator_rx) = unbounded_channel();
+        lobby.broadcast_to(spectator_tx);
+
+        lobby.play_match();
+
+        // every card is revealed once the round is over, so look at the first turn
+        let update = spectator_rx.try_recv().unwrap();
+        assert_eq!(
+            update.players.iter().collect::<Vec<_>>(),
+            lobby.player_names()
+        );
+        assert!(update
+            .public_log
+            .iter()
+            .all(|e| !matches!(e, Event::PickUp(_, Some(_), _))));
+        assert!(update
+            .full_log
+            .iter()
+            .any(|e| matches!(e, Event::PickUp(_, Some(_), _))));
+    }
+
+    #[test]
+    fn play_match_should_be_reproducible_with_the_same_seed() {
+        let play = |seed| {
+            let mut lobby = GameLobby::new(RuleSet::default(), seed);
+            for name in ["A", "B", "C", "D"] {
+                lobby.add_player(|| TestPlayer::new(name));
+            }
+            let winners = lobby.play_match();
+            let names = lobby
+                .player_names()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            (names, winners)
+        };
+
+        assert_eq!(play(7), play(7));
+    }
+
+    #[test]
+    fn replaying_the_record_should_reproduce_the_event_log() {
+        let observed_log = Arc::new(Mutex::new(vec![]));
+        let mut lobby = GameLobby::new(RuleSet::default(), 3);
+        let log = observed_log.clone();
+        lobby.add_player(move || TestPlayer::observing("Foo", log));
+        lobby.add_player(|| TestPlayer::new("Bar"));
+        lobby.add_player(|| TestPlayer::new("Baz"));
+        let (swap_tx, swap_rx) = unbounded_channel();
+        lobby.swap_seats_from(swap_rx);
+        for reason in [SwapReason::Takeover, SwapReason::Return] {
+            swap_tx
+                .send(SeatSwap {
+                    name: "Bar".to_string(),
+                    player: Box::new(TestPlayer::new("Bar")),
+                    reason,
+                })
+                .unwrap();
+        }
+        lobby.play_match();
+
+        let record: GameRecord =
+            serde_json::from_str(&serde_json::to_string(lobby.record()).unwrap()).unwrap();
+        let replayed_log = record.replay().unwrap();
+
+        assert_eq!(
+            GameState::filter_event(&replayed_log, None),
+            *observed_log.lock().unwrap()
+        );
+        assert_eq!(observed_log.lock().unwrap()[0], Event::MatchStarted(3));
+        assert_eq!(record.rounds[0].swaps.len(), 2);
+    }
+
     // Infra ----------------------------------------------------------------
 
     pub struct TestPlayer {
         pub data: PlayerData,
+        pub observed_log: Option<Arc<Mutex<Vec<Event>>>>,
     }
 
     impl TestPlayer {
         pub fn new(name: &str) -> Self {
             TestPlayer {
                 data: PlayerData::new(name.to_string()),
+                observed_log: None,
+            }
+        }
+
+        pub fn observing(name: &str, observed_log: Arc<Mutex<Vec<Event>>>) -> Self {
+            TestPlayer {
+                data: PlayerData::new(name.to_string()),
+                observed_log: Some(observed_log),
             }
         }
     }
@@ -107,17 +604,20 @@ mod tests {
             &mut self.data
         }
 
-        fn notify(&self, _game_log: &[Event], _players: &[&String]) {
-            todo!()
+        fn notify(&self, game_log: &[Event], _players: &[&String]) {
+            if let Some(observed_log) = &self.observed_log {
+                *observed_log.lock().unwrap() = game_log.to_vec();
+            }
         }
 
         fn obtain_action(
             &self,
             _players: &[&String],
             _game_log: &[Event],
-            _actions: &[Action],
+            actions: &[Action],
         ) -> usize {
-            todo!()
+            // always play the last valid action to never give up
+            actions.len() - 1
         }
     }
 }
@@ -1,22 +1,25 @@
-use crate::utils::VecExtensions;
+use crate::{rule_set::RuleSet, utils::VecExtensions};
 use itertools::{iproduct, Itertools};
 use royals_core::{
     card::Card,
-    events::{Action, ActionId, Event, EventEntry, EventVisibility, Play, PlayerId},
+    events::{Action, ActionError, ActionId, Event, EventEntry, EventVisibility, Play, PlayerId},
 };
-use std::{collections::HashSet, iter::once};
+use std::{cmp::Ordering, collections::HashSet, iter::once};
 use strum::IntoEnumIterator;
 
+#[derive(Clone, Default)]
 pub struct PlayerState {
     protected: bool,
     hand: Vec<Card>,
+    discarded: Vec<Card>,
 }
 
 impl PlayerState {
-    fn new() -> Self {
+    pub fn new() -> Self {
         PlayerState {
             protected: false,
             hand: vec![],
+            discarded: vec![],
         }
     }
     pub fn protected(&self) -> bool {
@@ -35,26 +38,72 @@ impl PlayerState {
         &mut self.hand
     }
 
+    pub fn discarded(&self) -> &Vec<Card> {
+        &self.discarded
+    }
+
+    pub fn discarded_mut(&mut self) -> &mut Vec<Card> {
+        &mut self.discarded
+    }
+
+    pub fn discarded_value(&self) -> usize {
+        self.discarded.iter().map(|c| c.value() as usize).sum()
+    }
+
+    /// Value of the hand card including the bonus of every folded Count
+    pub fn hand_value(&self) -> Option<u8> {
+        let counts = self.discarded.iter().filter(|&&c| c == Card::Count).count() as u8;
+        self.hand.first().map(|c| c.value() + counts)
+    }
+
+    /// Compares the hands at the end of the round. The Princess wins against the Bishop, folded
+    /// Counts or not.
+    pub fn compare_hands(&self, other: &PlayerState) -> Ordering {
+        match (self.hand.first(), other.hand.first()) {
+            (Some(&Card::Princess), Some(&Card::Bishop))
+            | (Some(&Card::Bishop), Some(&Card::Princess)) => self.hand[0].compare(&other.hand[0]),
+            _ => self.hand_value().cmp(&other.hand_value()),
+        }
+    }
+
     pub fn is_active(&self) -> bool {
         !&self.hand().is_empty()
     }
 }
 
-pub struct GameState<'a> {
+#[derive(Clone)]
+pub struct GameState {
     pub players: Vec<PlayerState>,
     pub played_card_count: usize,
     pub players_turn: PlayerId,
-    pub deck: &'a [Card],
+    pub deck: Vec<Card>,
+    pub burned_card: Option<Card>,
+    pub sycophant_target: Option<PlayerId>,
+    pub jester_bets: Vec<(PlayerId, PlayerId)>,
+    pub bonus_tokens: Vec<PlayerId>,
+    pub rules: RuleSet,
 }
 
-impl<'a> GameState<'a> {
-    pub fn new(player_count: usize, deck: &'a [Card], log: &mut Vec<EventEntry>) -> Self {
+impl GameState {
+    pub fn new(
+        player_count: usize,
+        starting_player: PlayerId,
+        deck: Vec<Card>,
+        rules: RuleSet,
+        log: &mut Vec<EventEntry>,
+    ) -> Self {
         let mut state = GameState {
             players: vec![],
             played_card_count: 0,
-            players_turn: 0,
+            players_turn: starting_player,
             deck,
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules,
         };
+        state.burn_cards(player_count, log);
         for i in 0..player_count {
             state.players.push(PlayerState::new());
             state.pick_up_card(i, log);
@@ -79,34 +128,68 @@ impl<'a> GameState<'a> {
 
     pub fn possible_actions(&self) -> Vec<Action> {
         // todo is there an alternative way to also iterate over None
-        let others = self.other_active_players();
-        let mut optional_players = others.iter().map(|p| Some(*p)).collect_vec();
+        let mut optional_players = (0..self.players.len()).map(Some).collect_vec();
         optional_players.push(None);
 
-        let mut optional_card = Card::iter().map(Some).collect_vec();
+        let mut optional_card = self.guessable_cards().into_iter().map(Some).collect_vec();
         optional_card.push(None);
 
-        iproduct!(Card::iter(), optional_players.iter(), optional_card.iter())
-            .map(|(card, &opponent, &guess)| {
-                Action::Play(Play {
-                    card,
-                    opponent,
-                    guess,
-                })
+        let hand = self.players[self.players_turn].hand();
+        Card::iter()
+            .filter(|card| hand.contains(card))
+            .flat_map(|card| {
+                let opponents = if card.needs_opponent() {
+                    optional_players.clone()
+                } else {
+                    vec![None]
+                };
+                let second_opponents = if card.allows_second_opponent() {
+                    optional_players.clone()
+                } else {
+                    vec![None]
+                };
+                let guesses = if card.needs_guess() {
+                    optional_card.clone()
+                } else {
+                    vec![None]
+                };
+                iproduct!(opponents, second_opponents, guesses).map(
+                    move |(opponent, second_opponent, guess)| {
+                        Action::Play(Play {
+                            card,
+                            opponent,
+                            second_opponent,
+                            guess,
+                        })
+                    },
+                )
             })
             .collect_vec()
     }
 
+    /// Cards of the current deck that can be guessed with the Guard or the Bishop
+    pub fn guessable_cards(&self) -> Vec<Card> {
+        Card::guessable()
+            .iter()
+            .filter(|c| self.deck.contains(c))
+            .cloned()
+            .collect_vec()
+    }
+
     pub fn is_valid(&self, action: &Action) -> bool {
-        if self.game_over() {
+        // a player can only act while holding the freshly picked up card
+        if self.players[self.players_turn].hand().len() < 2 {
             return false;
         }
         match action {
-            Action::GiveUp => true,
+            Action::GiveUp => self.rules.give_up_allowed,
             Action::Play(play) => {
                 if !self.players[self.players_turn].hand().contains(&play.card) {
                     return false;
                 }
+                if play.card == Card::Princess && !self.rules.princess_playable {
+                    return false;
+                }
                 if self.players[self.players_turn]
                     .hand()
                     .contains(&Card::Countess)
@@ -122,22 +205,66 @@ impl<'a> GameState<'a> {
                     return false;
                 }
 
+                if !play.card.allows_second_opponent() {
+                    if play.second_opponent.is_some() {
+                        return false;
+                    }
+                } else if play.card.needs_second_opponent()
+                    && play.opponent.is_some()
+                    && play.second_opponent.is_none()
+                {
+                    return false;
+                }
+                if play.second_opponent.is_some()
+                    && (play.opponent.is_none() || play.second_opponent == play.opponent)
+                {
+                    return false;
+                }
+
                 if !play.card.needs_guess() {
                     if play.guess.is_some() {
                         return false;
                     }
-                } else if !self.all_protected() && play.guess.is_none() {
+                } else if (play.opponent.is_some() || !self.all_protected()) && play.guess.is_none()
+                {
+                    // a player is only attacked with a guess, also when everybody is protected
                     return false;
                 }
+                if let Some(guess) = play.guess {
+                    if !self.guessable_cards().contains(&guess) {
+                        return false;
+                    }
+                }
 
                 if let Some(op) = play.opponent {
-                    if op == self.players_turn {
+                    if !self.is_valid_target(op) {
                         return false;
                     }
-                    if op >= self.players.len() {
+                }
+                if let Some(op) = play.second_opponent {
+                    // the cardinal may exchange the card of the player itself
+                    let own_exchange = play.card == Card::Cardinal && op == self.players_turn;
+                    if !own_exchange && !self.is_valid_target(op) {
                         return false;
                     }
-                    if !self.players[op].is_active() {
+                }
+
+                for &op in play.opponent.iter().chain(play.second_opponent.iter()) {
+                    // protected players may only be attacked by the rules which let the attacker
+                    // drop out, or get away with it if everybody else is protected as well
+                    if op != self.players_turn
+                        && self.players[op].protected()
+                        && !self.rules.drop_out_on_protected_attack
+                    {
+                        return false;
+                    }
+                }
+
+                if let Some(target) = self.forced_target() {
+                    if play.card.needs_opponent()
+                        && play.opponent != Some(target)
+                        && play.second_opponent != Some(target)
+                    {
                         return false;
                     }
                 }
@@ -146,6 +273,21 @@ impl<'a> GameState<'a> {
         }
     }
 
+    fn is_valid_target(&self, player_id: PlayerId) -> bool {
+        player_id != self.players_turn
+            && player_id < self.players.len()
+            && self.players[player_id].is_active()
+    }
+
+    /// Player which has to be targeted due to a previously played Sycophant
+    pub fn forced_target(&self) -> Option<PlayerId> {
+        self.sycophant_target.filter(|&target| {
+            target != self.players_turn
+                && self.players[target].is_active()
+                && !self.players[target].protected()
+        })
+    }
+
     pub fn active_players(&self) -> HashSet<PlayerId> {
         self.players
             .iter()
@@ -178,160 +320,128 @@ impl<'a> GameState<'a> {
     }
 
     pub fn game_over(&self) -> bool {
-        // last card is ussually not used
-        self.deck.len() - self.played_card_count <= 1 || self.active_players().len() <= 1
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::game_logic::{GameState, PlayerState};
-    use royals_core::card::Card;
-    use std::collections::HashSet;
-
-    #[test]
-    fn active_players_should_return_player_ids_with_non_empty_hand() {
-        let deck = &Card::deck();
-        let state = GameState {
-            deck,
-            players: vec![
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                },
-                PlayerState {
-                    protected: false,
-                    hand: vec![Card::King],
-                },
-            ],
-            played_card_count: 0,
-            players_turn: 0,
-        };
-
-        assert_eq!(state.active_players(), HashSet::from([1]));
+        self.deck.len() == self.played_card_count || self.active_players().len() <= 1
     }
 
-    #[test]
-    fn other_players_should_return_ids_of_others() {
-        let deck = &Card::deck();
-        let state = GameState {
-            deck,
-            players: vec![
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                },
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                },
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                },
-            ],
-            played_card_count: 0,
-            players_turn: 1, // second player turn
+    pub fn round_winners(&self) -> Vec<PlayerId> {
+        let tie_break = |p: &PlayerState| {
+            if self.rules.tie_break_by_discards {
+                p.discarded_value()
+            } else {
+                0
+            }
         };
-
-        assert_eq!(state.other_players(), HashSet::from([0, 2]));
-    }
-
-    #[test]
-    fn all_protected_should_return_true_if_no_other_active_player_is_unprotected() {
-        let deck = &Card::deck();
-        let state = GameState {
-            deck,
-            players: vec![
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                }, // inactive
-                PlayerState {
-                    protected: false,
-                    hand: vec![Card::King],
-                }, // players turn
-                PlayerState {
-                    protected: true,
-                    hand: vec![Card::Countess],
-                }, // protected
-            ],
-            played_card_count: 0,
-            players_turn: 1, // second players turn
+        let beats = |a: &PlayerState, b: &PlayerState| {
+            a.compare_hands(b).then(tie_break(a).cmp(&tie_break(b))) == Ordering::Greater
         };
-
-        assert_eq!(state.all_protected(), true);
+        let active = self.players.iter().filter(|p| p.is_active()).collect_vec();
+        // the Princess wins against the Bishop, so the best hand is the one nobody beats
+        let best_players = self
+            .players
+            .iter()
+            .enumerate()
+            .filter(|&(_, p)| p.is_active() && !active.iter().any(|other| beats(other, p)))
+            .map(|(i, _)| i)
+            .collect_vec();
+        if best_players.len() > 1 && !self.rules.shared_win_on_tie {
+            return vec![];
+        }
+        best_players
     }
 
-    #[test]
-    fn all_protected_should_return_false_if_at_least_one_other_active_player_is_unprotected() {
-        let deck = &Card::deck();
-        let state = GameState {
-            deck,
-            players: vec![
-                PlayerState {
-                    protected: false,
-                    hand: vec![],
-                }, // inactive
-                PlayerState {
-                    protected: false,
-                    hand: vec![Card::King],
-                }, // players turn
-                PlayerState {
-                    protected: true,
-                    hand: vec![Card::Countess],
-                }, // protected
-                PlayerState {
-                    protected: false,
-                    hand: vec![Card::Guard],
-                }, // unprotected
-            ],
-            played_card_count: 0,
-            players_turn: 1, // second players turn
-        };
-
-        assert_eq!(state.all_protected(), false);
+    /// Players receiving a token of affection at the end of the round, once per token
+    pub fn round_tokens(&self) -> Vec<PlayerId> {
+        let winners = self.round_winners();
+        let jesters = self
+            .jester_bets
+            .iter()
+            .filter(|(_, target)| winners.contains(target))
+            .map(|&(jester, _)| jester)
+            .collect_vec();
+        winners
+            .into_iter()
+            .chain(jesters)
+            .chain(self.bonus_tokens.iter().cloned())
+            .collect()
     }
 }
 
-impl GameState<'_> {
-    pub fn handle_action(&mut self, action: ActionId, log: &mut Vec<EventEntry>) {
+impl GameState {
+    /// Plays one of the valid actions, anything else leaves the state as it is
+    pub fn handle_action(
+        &mut self,
+        action: ActionId,
+        log: &mut Vec<EventEntry>,
+    ) -> Result<(), ActionError> {
         let (_, actions) = self.valid_actions();
-        if action < actions.len() {
-            match &actions[action] {
-                Action::GiveUp => {
-                    self.drop_player(self.players_turn, "Player gave up", log);
-                }
-                Action::Play(p) => {
-                    self.handle_play(p, log);
-                }
-            }
-            self.next_player_turn(log);
+        match actions.get(action) {
+            Some(Action::GiveUp) => self.drop_player(self.players_turn, "Player gave up", log),
+            Some(Action::Play(p)) => self.handle_play(p, log),
+            None => return Err(ActionError::Invalid(action)),
         }
+        self.next_player_turn(log);
+        Ok(())
     }
 
     pub fn filter_event(log: &[EventEntry], visible_to: Option<PlayerId>) -> Vec<Event> {
+        Self::mask_events(log, |visibility| match visibility {
+            EventVisibility::Public => true,
+            EventVisibility::Private(player) => visible_to.map_or(true, |v| v == *player),
+            EventVisibility::Hidden => visible_to.is_none(),
+        })
+    }
+
+    /// The log as seen by somebody who does not sit at the table
+    pub fn public_events(log: &[EventEntry]) -> Vec<Event> {
+        Self::mask_events(log, |visibility| *visibility == EventVisibility::Public)
+    }
+
+    fn mask_events(log: &[EventEntry], visible: impl Fn(&EventVisibility) -> bool) -> Vec<Event> {
         log.iter()
-            .map(|e| match e.visibility {
-                EventVisibility::Public => e.event.clone(),
-                EventVisibility::Private(player) => {
-                    if visible_to.is_none() || player == visible_to.unwrap() {
-                        e.event.clone()
-                    } else {
-                        match e.event {
-                            Event::PickUp(p, _, s) => Event::PickUp(p, None, s),
-                            Event::LearnedCard(p, _) => Event::LearnedCard(p, None),
-                            _ => e.event.clone(),
-                        }
+            .map(|e| {
+                if visible(&e.visibility) {
+                    e.event.clone()
+                } else {
+                    match e.event {
+                        Event::PickUp(p, _, s) => Event::PickUp(p, None, s),
+                        Event::LearnedCard(p, _) => Event::LearnedCard(p, None),
+                        Event::BurnFaceDown(_) => Event::BurnFaceDown(None),
+                        _ => e.event.clone(),
                     }
                 }
             })
             .collect()
     }
 
-    pub fn pick_up_card(&mut self, player_id: PlayerId, log: &mut Vec<EventEntry>) {
-        let next_card = self.deck[self.played_card_count];
+    pub fn burn_cards(&mut self, player_count: usize, log: &mut Vec<EventEntry>) {
+        let burned_card = self.deck[self.played_card_count];
         self.played_card_count += 1;
+        self.burned_card = Some(burned_card);
+        log.push(EventEntry {
+            visibility: EventVisibility::Hidden,
+            event: Event::BurnFaceDown(Some(burned_card)),
+        });
+
+        if player_count == 2 {
+            for _ in 0..3 {
+                let card = self.deck[self.played_card_count];
+                self.played_card_count += 1;
+                log.push(EventEntry {
+                    visibility: EventVisibility::Public,
+                    event: Event::BurnFaceUp(card),
+                });
+            }
+        }
+    }
+
+    pub fn pick_up_card(&mut self, player_id: PlayerId, log: &mut Vec<EventEntry>) {
+        // the face down burned card is used once the deck is empty
+        let next_card = if self.played_card_count < self.deck.len() {
+            self.played_card_count += 1;
+            self.deck[self.played_card_count - 1]
+        } else {
+            self.burned_card.take().unwrap()
+        };
         log.push(EventEntry {
             visibility: EventVisibility::Private(player_id),
             event: Event::PickUp(
@@ -343,8 +453,29 @@ impl GameState<'_> {
         self.players[player_id].hand_mut().push(next_card);
     }
 
+    /// Shows a player the card they received in an exchange
+    fn reveal_own_card(&self, player_id: PlayerId, log: &mut Vec<EventEntry>) {
+        log.push(EventEntry {
+            visibility: EventVisibility::Private(player_id),
+            event: Event::LearnedCard(player_id, Some(self.players[player_id].hand()[0])),
+        });
+    }
+
+    /// The card of another player knocks the player out, which earns them a token if they have
+    /// discarded the Constable
+    pub fn knock_out(&mut self, player_id: PlayerId, reason: &str, log: &mut Vec<EventEntry>) {
+        if self.players[player_id]
+            .discarded()
+            .contains(&Card::Constable)
+        {
+            self.bonus_tokens.push(player_id);
+        }
+        self.drop_player(player_id, reason, log);
+    }
+
     pub fn drop_player(&mut self, player_id: PlayerId, reason: &str, log: &mut Vec<EventEntry>) {
         while let Some(op_card) = self.players[player_id].hand_mut().pop() {
+            self.players[player_id].discarded_mut().push(op_card);
             log.push(EventEntry {
                 visibility: EventVisibility::Public,
                 event: Event::Fold(player_id, op_card, reason.to_string()),
@@ -356,6 +487,21 @@ impl GameState<'_> {
         });
     }
 
+    pub fn fold_and_pick_up(
+        &mut self,
+        player_id: PlayerId,
+        reason: &str,
+        log: &mut Vec<EventEntry>,
+    ) {
+        let folded = self.players[player_id].hand_mut().pop().unwrap();
+        self.players[player_id].discarded_mut().push(folded);
+        log.push(EventEntry {
+            visibility: EventVisibility::Public,
+            event: Event::Fold(player_id, folded, reason.to_string()),
+        });
+        self.pick_up_card(player_id, log);
+    }
+
     pub fn next_player_turn(&mut self, log: &mut Vec<EventEntry>) {
         self.players_turn = (self.players_turn + 1) % self.players.len();
         while !self.players[self.players_turn].is_active() {
@@ -369,31 +515,21 @@ impl GameState<'_> {
     }
 
     pub fn wrap_up_round(&mut self, log: &mut Vec<EventEntry>) {
-        let mut best_players: Vec<PlayerId> = vec![];
-        let mut best_card: Option<Card> = None;
         for (i, p) in self.players.iter().enumerate() {
             if let Some(player_card) = p.hand().first() {
                 log.push(EventEntry {
                     visibility: EventVisibility::Public,
                     event: Event::Fold(i, *player_card, "game is finished".to_string()),
                 });
-                if let Some(card) = best_card {
-                    if card < *player_card {
-                        best_players = vec![i];
-                        best_card = Some(*player_card);
-                    } else if card == *player_card {
-                        best_players.push(i);
-                    }
-                } else {
-                    best_players = vec![i];
-                    best_card = Some(*player_card);
-                }
             }
         }
 
         log.push(EventEntry {
             visibility: EventVisibility::Public,
-            event: Event::Winner(best_players),
+            event: Event::Winner(
+                self.round_winners(),
+                self.players.iter().map(|p| p.discarded_value()).collect(),
+            ),
         });
 
         for e in log {
@@ -406,25 +542,41 @@ impl GameState<'_> {
             .hand_mut()
             .remove_first_where(|&card| card == p.card)
             .unwrap();
+        self.players[self.players_turn].discarded_mut().push(card);
 
         log.push(EventEntry {
             visibility: EventVisibility::Public,
             event: Event::Play(self.players_turn, p.clone()),
         });
-        if let Some(opponent) = p.opponent {
+        self.sycophant_target = None;
+        for &opponent in p.opponent.iter().chain(p.second_opponent.iter()) {
             // do not attack protected player
-            if self.players[opponent].protected() && !self.all_protected() {
+            if opponent != self.players_turn
+                && self.players[opponent].protected()
+                && !self.all_protected()
+            {
                 self.drop_player(self.players_turn, "attacked a protected player", log);
                 return;
             }
         }
         self.players[self.players_turn].set_protected(false);
         match card {
+            Card::Jester => {
+                if let Some(op) = p.opponent {
+                    self.jester_bets.push((self.players_turn, op));
+                }
+            }
+            Card::Assassin => {}
             Card::Guard => {
                 if let Some(op) = p.opponent {
-                    let g = p.guess.unwrap();
-                    if self.players[op].hand()[0] == g {
-                        self.drop_player(op, "opponent guessed the hand card", log)
+                    if self.players[op].hand()[0] == Card::Assassin {
+                        self.knock_out(self.players_turn, "attacked the assassin", log);
+                        self.fold_and_pick_up(op, "revealed to attack the guard", log);
+                    } else {
+                        let g = p.guess.unwrap();
+                        if self.players[op].hand()[0].value() == g.value() {
+                            self.knock_out(op, "opponent guessed the hand card", log)
+                        }
                     }
                 }
             }
@@ -436,52 +588,552 @@ impl GameState<'_> {
                     });
                 }
             }
+            Card::Cardinal => {
+                if let (Some(first), Some(second)) = (p.opponent, p.second_opponent) {
+                    let first_hand = std::mem::take(self.players[first].hand_mut());
+                    let second_hand =
+                        std::mem::replace(self.players[second].hand_mut(), first_hand);
+                    *self.players[first].hand_mut() = second_hand;
+                    log.push(EventEntry {
+                        visibility: EventVisibility::Private(self.players_turn),
+                        event: Event::LearnedCard(first, Some(self.players[first].hand()[0])),
+                    });
+                    self.reveal_own_card(first, log);
+                    self.reveal_own_card(second, log);
+                }
+            }
             Card::Baron => {
                 if let Some(op) = p.opponent {
                     let op_card = self.players[op].hand()[0];
                     let player_card = self.players[self.players_turn].hand()[0];
-                    if op_card < player_card {
-                        self.drop_player(op, "smaller card then opponent", log);
-                    } else if player_card < op_card {
-                        self.drop_player(self.players_turn, "smaller card then opponent", log);
+                    match op_card.compare(&player_card) {
+                        Ordering::Less => self.knock_out(op, "smaller card then opponent", log),
+                        Ordering::Greater => {
+                            self.knock_out(self.players_turn, "smaller card then opponent", log)
+                        }
+                        Ordering::Equal => {}
                     }
                 }
             }
+            Card::Baroness => {
+                for &op in p.opponent.iter().chain(p.second_opponent.iter()) {
+                    log.push(EventEntry {
+                        visibility: EventVisibility::Private(self.players_turn),
+                        event: Event::LearnedCard(op, Some(self.players[op].hand()[0])),
+                    });
+                }
+            }
             Card::Maid => {
                 self.players[self.players_turn].set_protected(true);
             }
+            Card::Sycophant => {
+                self.sycophant_target = p.opponent;
+            }
             Card::Prince => {
                 if let Some(op) = p.opponent {
                     if self.players[op].hand()[0] == Card::Princess {
-                        self.drop_player(op, "forced to play the princess", log);
+                        self.knock_out(op, "forced to play the princess", log);
                     } else {
-                        let folded = self.players[op].hand_mut().pop().unwrap();
-                        log.push(EventEntry {
-                            visibility: EventVisibility::Public,
-                            event: Event::Fold(
-                                op,
-                                folded,
-                                "opponent has played prince to force it".to_string(),
-                            ),
-                        });
-                        self.pick_up_card(op, log);
+                        self.fold_and_pick_up(op, "opponent has played prince to force it", log);
                     }
                 }
             }
+            Card::Count => {}
             Card::King => {
                 if let Some(op) = p.opponent {
                     let op_card = self.players[op].hand_mut().pop().unwrap();
                     let player_card = self.players[self.players_turn].hand_mut().pop().unwrap();
                     self.players[op].hand_mut().push(player_card);
                     self.players[self.players_turn].hand_mut().push(op_card);
+                    self.reveal_own_card(op, log);
+                    self.reveal_own_card(self.players_turn, log);
                 }
             }
+            Card::Constable => {}
             Card::Countess => {}
+            Card::DowagerQueen => {
+                if let Some(op) = p.opponent {
+                    let op_card = self.players[op].hand()[0];
+                    let player_card = self.players[self.players_turn].hand()[0];
+                    match op_card.compare(&player_card) {
+                        Ordering::Greater => self.knock_out(op, "higher card then opponent", log),
+                        Ordering::Less => {
+                            self.knock_out(self.players_turn, "higher card then opponent", log)
+                        }
+                        Ordering::Equal => {}
+                    }
+                }
+            }
             Card::Princess => self.drop_player(
                 self.players_turn,
                 "playing the princess is equivalent to giving up",
                 log,
             ),
+            Card::Bishop => {
+                if let Some(op) = p.opponent {
+                    let g = p.guess.unwrap();
+                    if self.players[op].hand()[0].value() == g.value() {
+                        self.bonus_tokens.push(self.players_turn);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game_logic::{GameState, PlayerState},
+        rule_set::RuleSet,
+    };
+    use royals_core::{
+        card::Card,
+        events::{Action, ActionError, Event, Play, PlayerId},
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn active_players_should_return_player_ids_with_non_empty_hand() {
+        let deck = &Card::deck();
+        let state = GameState {
+            deck: deck.to_vec(),
+            players: vec![
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                },
+                PlayerState {
+                    protected: false,
+                    hand: vec![Card::King],
+                    discarded: vec![],
+                },
+            ],
+            played_card_count: 0,
+            players_turn: 0,
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules: RuleSet::default(),
+        };
+
+        assert_eq!(state.active_players(), HashSet::from([1]));
+    }
+
+    #[test]
+    fn other_players_should_return_ids_of_others() {
+        let deck = &Card::deck();
+        let state = GameState {
+            deck: deck.to_vec(),
+            players: vec![
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                },
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                },
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                },
+            ],
+            played_card_count: 0,
+            players_turn: 1, // second player turn
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules: RuleSet::default(),
+        };
+
+        assert_eq!(state.other_players(), HashSet::from([0, 2]));
+    }
+
+    #[test]
+    fn all_protected_should_return_true_if_no_other_active_player_is_unprotected() {
+        let deck = &Card::deck();
+        let state = GameState {
+            deck: deck.to_vec(),
+            players: vec![
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                }, // inactive
+                PlayerState {
+                    protected: false,
+                    hand: vec![Card::King],
+                    discarded: vec![],
+                }, // players turn
+                PlayerState {
+                    protected: true,
+                    hand: vec![Card::Countess],
+                    discarded: vec![],
+                }, // protected
+            ],
+            played_card_count: 0,
+            players_turn: 1, // second players turn
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules: RuleSet::default(),
+        };
+
+        assert!(state.all_protected());
+    }
+
+    #[test]
+    fn all_protected_should_return_false_if_at_least_one_other_active_player_is_unprotected() {
+        let deck = &Card::deck();
+        let state = GameState {
+            deck: deck.to_vec(),
+            players: vec![
+                PlayerState {
+                    protected: false,
+                    hand: vec![],
+                    discarded: vec![],
+                }, // inactive
+                PlayerState {
+                    protected: false,
+                    hand: vec![Card::King],
+                    discarded: vec![],
+                }, // players turn
+                PlayerState {
+                    protected: true,
+                    hand: vec![Card::Countess],
+                    discarded: vec![],
+                }, // protected
+                PlayerState {
+                    protected: false,
+                    hand: vec![Card::Guard],
+                    discarded: vec![],
+                }, // unprotected
+            ],
+            played_card_count: 0,
+            players_turn: 1, // second players turn
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules: RuleSet::default(),
+        };
+
+        assert!(!state.all_protected());
+    }
+
+    #[test]
+    fn new_should_burn_one_card_face_down() {
+        let deck = &Card::deck();
+        let mut log = vec![];
+        let state = GameState::new(4, 0, deck.to_vec(), RuleSet::default(), &mut log);
+
+        assert_eq!(state.burned_card, Some(deck[0]));
+        assert_eq!(
+            GameState::filter_event(&log, Some(0))[0],
+            Event::BurnFaceDown(None)
+        );
+        // 1 burned card, 4 dealt cards and 1 picked up by the starting player
+        assert_eq!(state.played_card_count, 6);
+    }
+
+    #[test]
+    fn handle_action_should_refuse_actions_out_of_range() {
+        let mut log = vec![];
+        let mut state = GameState::new(2, 0, Card::deck().to_vec(), RuleSet::default(), &mut log);
+        let (_, actions) = state.valid_actions();
+        let log_length = log.len();
+
+        let result = state.handle_action(actions.len(), &mut log);
+
+        assert_eq!(result, Err(ActionError::Invalid(actions.len())));
+        assert_eq!(log.len(), log_length);
+        assert_eq!(state.valid_actions().1, actions);
+    }
+
+    #[test]
+    fn new_should_burn_three_additional_cards_face_up_for_two_players() {
+        let deck = &Card::deck();
+        let mut log = vec![];
+        let state = GameState::new(2, 0, deck.to_vec(), RuleSet::default(), &mut log);
+
+        let face_up = GameState::filter_event(&log, Some(0))
+            .into_iter()
+            .filter(|e| matches!(e, Event::BurnFaceUp(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            face_up,
+            vec![
+                Event::BurnFaceUp(deck[1]),
+                Event::BurnFaceUp(deck[2]),
+                Event::BurnFaceUp(deck[3])
+            ]
+        );
+        assert_eq!(state.played_card_count, 7);
+    }
+
+    #[test]
+    fn public_events_should_hide_every_hand() {
+        let deck = &Card::deck();
+        let mut log = vec![];
+        GameState::new(4, 0, deck.to_vec(), RuleSet::default(), &mut log);
+
+        let public = GameState::public_events(&log);
+
+        assert_eq!(public[0], Event::BurnFaceDown(None));
+        assert!(public
+            .iter()
+            .all(|e| !matches!(e, Event::PickUp(_, Some(_), _))));
+        assert!(GameState::filter_event(&log, Some(0))
+            .iter()
+            .any(|e| matches!(e, Event::PickUp(0, Some(_), _))));
+    }
+
+    #[test]
+    fn guard_attacking_the_assassin_should_drop_out() {
+        let deck = &Card::premium_deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::Guard, Card::King], vec![Card::Assassin]],
+            0,
+        );
+        let mut log = vec![];
+
+        state.handle_play(
+            &Play {
+                card: Card::Guard,
+                opponent: Some(1),
+                second_opponent: None,
+                guess: Some(Card::Priest),
+            },
+            &mut log,
+        );
+
+        assert!(!state.players[0].is_active());
+        assert_eq!(state.players[1].hand(), &vec![deck[0]]);
+        assert!(state.players[1].discarded().contains(&Card::Assassin));
+    }
+
+    #[test]
+    fn round_winners_should_add_one_per_folded_count() {
+        let deck = &Card::premium_deck();
+        let mut state = state_with_hands(deck, vec![vec![Card::King], vec![Card::Prince]], 0);
+        state.players[1].discarded_mut().push(Card::Count);
+        state.players[1].discarded_mut().push(Card::Count);
+
+        assert_eq!(state.round_winners(), vec![1]);
+    }
+
+    #[test]
+    fn the_princess_should_win_against_the_bishop() {
+        let deck = &Card::premium_deck();
+        let state = state_with_hands(
+            deck,
+            vec![
+                vec![Card::Bishop],
+                vec![Card::Princess],
+                vec![Card::Countess],
+            ],
+            0,
+        );
+
+        assert_eq!(state.round_winners(), vec![1]);
+
+        let mut log = vec![];
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::Baron, Card::Bishop], vec![Card::Princess]],
+            0,
+        );
+        state.handle_play(
+            &Play {
+                card: Card::Baron,
+                opponent: Some(1),
+                second_opponent: None,
+                guess: None,
+            },
+            &mut log,
+        );
+
+        assert!(!state.players[0].is_active());
+        assert!(state.players[1].is_active());
+    }
+
+    #[test]
+    fn round_tokens_should_include_jester_bets_on_the_winner() {
+        let deck = &Card::premium_deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::Guard], vec![Card::Princess], vec![Card::Baron]],
+            0,
+        );
+        state.jester_bets = vec![(0, 1), (2, 0)];
+
+        assert_eq!(state.round_tokens(), vec![1, 0]);
+    }
+
+    #[test]
+    fn is_valid_should_honour_the_rule_set() {
+        let deck = &Card::deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::Princess, Card::Guard], vec![Card::King]],
+            0,
+        );
+        state.players[1].set_protected(true);
+        state.players.push(PlayerState {
+            protected: false,
+            hand: vec![Card::Maid],
+            discarded: vec![],
+        });
+        let princess = Action::Play(Play {
+            card: Card::Princess,
+            opponent: None,
+            second_opponent: None,
+            guess: None,
+        });
+        let attack_protected = Action::Play(Play {
+            card: Card::Guard,
+            opponent: Some(1),
+            second_opponent: None,
+            guess: Some(Card::Priest),
+        });
+        assert!(state.is_valid(&Action::GiveUp));
+        assert!(state.is_valid(&princess));
+        assert!(state.is_valid(&attack_protected));
+
+        state.rules = RuleSet {
+            drop_out_on_protected_attack: false,
+            princess_playable: false,
+            shared_win_on_tie: true,
+            tie_break_by_discards: true,
+            give_up_allowed: false,
+        };
+
+        assert!(!state.is_valid(&Action::GiveUp));
+        assert!(!state.is_valid(&princess));
+        assert!(!state.is_valid(&attack_protected));
+    }
+
+    #[test]
+    fn is_valid_should_allow_attacking_when_everybody_is_protected() {
+        let deck = &Card::deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::Priest, Card::Guard], vec![Card::King]],
+            0,
+        );
+        state.players[1].set_protected(true);
+        let attack_protected = Action::Play(Play {
+            card: Card::Priest,
+            opponent: Some(1),
+            second_opponent: None,
+            guess: None,
+        });
+
+        assert!(state.is_valid(&attack_protected));
+        assert!(!state.is_valid(&Action::Play(Play {
+            card: Card::Guard,
+            opponent: Some(1),
+            second_opponent: None,
+            guess: None,
+        })));
+
+        state.rules.drop_out_on_protected_attack = false;
+
+        assert!(!state.is_valid(&attack_protected));
+    }
+
+    #[test]
+    fn round_winners_should_break_ties_by_discarded_cards() {
+        let deck = &Card::premium_deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![vec![Card::King], vec![Card::Constable], vec![Card::Guard]],
+            0,
+        );
+        state.players[0].discarded_mut().push(Card::Guard);
+        state.players[1].discarded_mut().push(Card::Priest);
+        state.players[2].discarded_mut().push(Card::Princess);
+
+        assert_eq!(state.round_winners(), vec![1]);
+
+        state.players[0].discarded_mut().push(Card::Guard);
+
+        assert_eq!(state.round_winners(), vec![0, 1]);
+
+        state.rules.tie_break_by_discards = false;
+        state.players[0].discarded_mut().clear();
+
+        assert_eq!(state.round_winners(), vec![0, 1]);
+    }
+
+    #[test]
+    fn only_knocked_out_players_should_earn_the_constable_token() {
+        let deck = &Card::premium_deck();
+        let mut state = state_with_hands(
+            deck,
+            vec![
+                vec![Card::Guard, Card::Priest],
+                vec![Card::King],
+                vec![Card::Baron],
+            ],
+            0,
+        );
+        state.players[0].discarded_mut().push(Card::Constable);
+        state.players[1].discarded_mut().push(Card::Constable);
+        let mut log = vec![];
+        let (_, actions) = state.valid_actions();
+        let give_up = actions.iter().position(|a| *a == Action::GiveUp).unwrap();
+
+        state.handle_action(give_up, &mut log).unwrap();
+
+        assert!(!state.players[0].is_active());
+        assert!(state.bonus_tokens.is_empty());
+
+        state.knock_out(1, "opponent guessed the hand card", &mut log);
+
+        assert_eq!(state.bonus_tokens, vec![1]);
+    }
+
+    #[test]
+    fn round_winners_should_be_empty_on_tie_without_shared_wins() {
+        let deck = &Card::deck();
+        let mut state = state_with_hands(deck, vec![vec![Card::King], vec![Card::King]], 0);
+        assert_eq!(state.round_winners(), vec![0, 1]);
+
+        state.rules.shared_win_on_tie = false;
+
+        assert!(state.round_winners().is_empty());
+    }
+
+    // Infra ----------------------------------------------------------------
+
+    fn state_with_hands(deck: &[Card], hands: Vec<Vec<Card>>, players_turn: PlayerId) -> GameState {
+        GameState {
+            deck: deck.to_vec(),
+            players: hands
+                .into_iter()
+                .map(|hand| PlayerState {
+                    protected: false,
+                    hand,
+                    discarded: vec![],
+                })
+                .collect(),
+            played_card_count: 0,
+            players_turn,
+            burned_card: None,
+            sycophant_target: None,
+            jester_bets: vec![],
+            bonus_tokens: vec![],
+            rules: RuleSet::default(),
         }
     }
 }
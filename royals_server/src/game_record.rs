@@ -0,0 +1,122 @@
+use crate::{
+    game_lobby::{GameLobby, SwapReason},
+    game_logic::GameState,
+    rule_set::RuleSet,
+};
+use royals_core::{
+    card::Card,
+    events::{Action, Event, EventEntry, EventVisibility, PlayerId},
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundRecord {
+    pub seed: u64,
+    pub starting_player: PlayerId,
+    pub deck: Vec<Card>,
+    pub actions: Vec<Action>,
+    /// Indices of the actions chosen for players who ran out of time
+    #[serde(default)]
+    pub timeouts: Vec<usize>,
+    #[serde(default)]
+    pub swaps: Vec<SwapRecord>,
+}
+
+/// A seat changing hands before the action with the given index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwapRecord {
+    pub action: usize,
+    pub seat: PlayerId,
+    pub reason: SwapReason,
+}
+
+/// Everything needed to reproduce the event log of a match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub rules: RuleSet,
+    pub players: Vec<String>,
+    pub rounds: Vec<RoundRecord>,
+}
+
+impl GameRecord {
+    pub fn new(seed: u64, rules: RuleSet, players: Vec<String>) -> Self {
+        GameRecord {
+            seed,
+            rules,
+            players,
+            rounds: vec![],
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Feeds the recorded actions back through the game logic and returns the resulting log.
+    pub fn replay(&self) -> Result<Vec<EventEntry>, String> {
+        let mut game_log: Vec<EventEntry> = vec![EventEntry {
+            visibility: EventVisibility::Public,
+            event: Event::MatchStarted(self.seed),
+        }];
+        let tokens_to_win = GameLobby::tokens_to_win(self.players.len());
+        let mut tokens = vec![0; self.players.len()];
+
+        for (i, round) in self.rounds.iter().enumerate() {
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: Event::RoundStarted(i + 1, round.starting_player, round.seed),
+            });
+
+            let mut state = GameState::new(
+                self.players.len(),
+                round.starting_player,
+                round.deck.clone(),
+                self.rules.clone(),
+                &mut game_log,
+            );
+            for (i, action) in round.actions.iter().enumerate() {
+                Self::replay_swaps(round, i, &mut game_log);
+                let (players_turn, actions) = state.valid_actions();
+                if let (true, Some(player)) = (round.timeouts.contains(&i), players_turn) {
+                    game_log.push(EventEntry {
+                        visibility: EventVisibility::Public,
+                        event: Event::TurnTimedOut(player),
+                    });
+                }
+                let action_id = actions
+                    .iter()
+                    .position(|a| a == action)
+                    .ok_or(format!("Recorded action {:?} is not valid", action))?;
+                state
+                    .handle_action(action_id, &mut game_log)
+                    .map_err(|e| e.to_string())?;
+            }
+            Self::replay_swaps(round, round.actions.len(), &mut game_log);
+
+            GameLobby::award_tokens(
+                &mut tokens,
+                tokens_to_win,
+                state.round_tokens(),
+                &mut game_log,
+            );
+        }
+        Ok(game_log)
+    }
+
+    fn replay_swaps(round: &RoundRecord, action: usize, game_log: &mut Vec<EventEntry>) {
+        for swap in round.swaps.iter().filter(|swap| swap.action == action) {
+            game_log.push(EventEntry {
+                visibility: EventVisibility::Public,
+                event: swap.reason.event(swap.seat),
+            });
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{
+    card_counting_computer::Knowledge,
+    game_logic::{GameState, PlayerState},
+    player::{Player, PlayerData},
+    rule_set::RuleSet,
+};
+use itertools::Itertools;
+use rand::{seq::SliceRandom, Rng};
+use royals_core::{
+    card::Card,
+    events::{Action, Event, EventEntry, PlayerId},
+};
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+static COMPUTER_NAMES: &[&str] = &["Searcher Golf", "Searcher Hotel", "Searcher India"];
+static COMPUTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Exploration constant of the upper confidence bound
+const EXPLORATION: f64 = 0.7;
+
+/// How long the search may run for a single move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchBudget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+impl FromStr for SearchBudget {
+    type Err = String;
+
+    /// Parses `500` as iterations and `50ms` as time per move.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |_| format!("Invalid search budget {}", s);
+        match s.strip_suffix("ms") {
+            Some(millis) => Ok(SearchBudget::Time(Duration::from_millis(
+                millis.parse().map_err(invalid)?,
+            ))),
+            None => Ok(SearchBudget::Iterations(s.parse().map_err(invalid)?)),
+        }
+    }
+}
+
+/// Computer player running an information set Monte Carlo tree search. Each iteration samples
+/// the hidden cards consistent with what the player has seen and plays the round to its end.
+pub struct IsmctsComputer {
+    pub data: PlayerData,
+    pub budget: SearchBudget,
+    /// Rules assumed while simulating
+    pub rules: RuleSet,
+}
+
+impl IsmctsComputer {
+    pub fn new() -> IsmctsComputer {
+        IsmctsComputer::with_budget(SearchBudget::Iterations(1000))
+    }
+
+    pub fn with_budget(budget: SearchBudget) -> IsmctsComputer {
+        let my_id = COMPUTER_COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = COMPUTER_NAMES[my_id % COMPUTER_NAMES.len()].to_string();
+        IsmctsComputer {
+            data: PlayerData::new(name),
+            budget,
+            rules: RuleSet::default(),
+        }
+    }
+
+    fn search(&self, knowledge: &Knowledge, player_count: usize) -> Option<Action> {
+        let mut rng = rand::thread_rng();
+        let mut tree = vec![Node::new(None, knowledge.me, None)];
+        let start = Instant::now();
+        let mut iteration = 0;
+        while match self.budget {
+            SearchBudget::Iterations(n) => iteration < n,
+            SearchBudget::Time(duration) => start.elapsed() < duration,
+        } {
+            iteration += 1;
+            let Some(mut state) = self.determinize(knowledge, player_count, &mut rng) else {
+                continue;
+            };
+            let mut log: Vec<EventEntry> = vec![];
+
+            // selection and expansion
+            let mut node = 0;
+            while !state.game_over() {
+                let actions = candidate_actions(&state);
+                if actions.is_empty() {
+                    break;
+                }
+                let untried = actions
+                    .iter()
+                    .filter(|a| {
+                        !tree[node]
+                            .children
+                            .iter()
+                            .any(|&c| tree[c].action.as_ref() == Some(a))
+                    })
+                    .collect_vec();
+                if let Some(&action) = untried.choose(&mut rng) {
+                    let child = tree.len();
+                    tree.push(Node::new(
+                        Some(action.clone()),
+                        state.players_turn,
+                        Some(node),
+                    ));
+                    tree[node].children.push(child);
+                    apply(&mut state, action, &mut log);
+                    node = child;
+                    break;
+                }
+                let available = tree[node]
+                    .children
+                    .iter()
+                    .cloned()
+                    .filter(|&c| actions.iter().any(|a| tree[c].action.as_ref() == Some(a)))
+                    .collect_vec();
+                for &c in &available {
+                    tree[c].availability += 1;
+                }
+                let Some(selected) = available
+                    .into_iter()
+                    .max_by(|&a, &b| tree[a].ucb().total_cmp(&tree[b].ucb()))
+                else {
+                    break;
+                };
+                let action = tree[selected].action.clone().unwrap();
+                apply(&mut state, &action, &mut log);
+                node = selected;
+            }
+
+            // simulation
+            while !state.game_over() {
+                let actions = candidate_actions(&state);
+                let Some(action) = actions.choose(&mut rng) else {
+                    break;
+                };
+                apply(&mut state, action, &mut log);
+            }
+
+            // backpropagation
+            let winners = state.round_winners();
+            let mut current = Some(node);
+            while let Some(n) = current {
+                tree[n].visits += 1;
+                if winners.contains(&tree[n].player) {
+                    tree[n].reward += 1.0 / winners.len() as f64;
+                }
+                current = tree[n].parent;
+            }
+        }
+
+        tree[0]
+            .children
+            .iter()
+            .max_by_key(|&&c| tree[c].visits)
+            .and_then(|&c| tree[c].action.clone())
+    }
+
+    /// Samples a game state in which the unseen cards are distributed randomly among the hands
+    /// of the opponents, the deck and the burned card.
+    fn determinize(
+        &self,
+        knowledge: &Knowledge,
+        player_count: usize,
+        rng: &mut impl Rng,
+    ) -> Option<GameState> {
+        let mut pool = knowledge
+            .unseen
+            .iter()
+            .flat_map(|(&card, &count)| std::iter::repeat(card).take(count))
+            .collect_vec();
+        pool.shuffle(rng);
+
+        let mut players = vec![];
+        for id in 0..player_count {
+            let mut player = PlayerState::new();
+            if let Some(discarded) = knowledge.discarded.get(&id) {
+                player.discarded_mut().clone_from(discarded);
+            }
+            player.set_protected(knowledge.protected.contains(&id));
+            if id == knowledge.me {
+                player.hand_mut().clone_from(&knowledge.hand);
+            } else if !knowledge.dropped.contains(&id) {
+                let card = match knowledge.known.get(&id) {
+                    Some(&card) => card,
+                    None => pool.pop()?,
+                };
+                player.hand_mut().push(card);
+            }
+            players.push(player);
+        }
+
+        let remaining = pool.split_off(pool.len().checked_sub(knowledge.remaining)?);
+        // the burned card may have been picked up already, which the simulation can not follow
+        let burned_card = Some(pool.pop()?);
+        // the played part of the deck only matters for the guessable cards
+        let mut deck = Card::deck_for(player_count);
+        for card in &remaining {
+            if let Some(i) = deck.iter().position(|c| c == card) {
+                deck.remove(i);
+            }
+        }
+        let played_card_count = deck.len();
+        deck.extend(remaining);
+
+        let bonus_tokens = knowledge
+            .dropped
+            .iter()
+            .filter(|id| players[**id].discarded().contains(&Card::Constable))
+            .cloned()
+            .collect_vec();
+
+        Some(GameState {
+            players,
+            played_card_count,
+            players_turn: knowledge.me,
+            deck,
+            burned_card,
+            sycophant_target: knowledge.sycophant_target,
+            jester_bets: knowledge.jester_bets.clone(),
+            bonus_tokens,
+            rules: self.rules.clone(),
+        })
+    }
+}
+
+impl Default for IsmctsComputer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for IsmctsComputer {
+    fn data(&self) -> &PlayerData {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        &mut self.data
+    }
+
+    fn notify(&self, _game_log: &[Event], _players: &[&String]) {}
+
+    fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        if valid_actions.len() <= 1 {
+            return 0;
+        }
+        let knowledge = Knowledge::from_log(players.len(), game_log);
+        let searched = self.search(&knowledge, players.len());
+        searched
+            .and_then(|action| valid_actions.iter().position(|a| *a == action))
+            .unwrap_or_else(|| {
+                // the log did not allow for a consistent sample, fall back to counting cards
+                valid_actions
+                    .iter()
+                    .map(|action| knowledge.score(action))
+                    .position_max_by(|a, b| a.total_cmp(b))
+                    .unwrap_or(0)
+            })
+    }
+}
+
+struct Node {
+    action: Option<Action>,
+    /// Player who took the action leading to this node
+    player: PlayerId,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    visits: usize,
+    /// Number of times this node could have been selected
+    availability: usize,
+    reward: f64,
+}
+
+impl Node {
+    fn new(action: Option<Action>, player: PlayerId, parent: Option<usize>) -> Self {
+        Node {
+            action,
+            player,
+            parent,
+            children: vec![],
+            visits: 0,
+            availability: 1,
+            reward: 0.0,
+        }
+    }
+
+    fn ucb(&self) -> f64 {
+        let visits = self.visits.max(1) as f64;
+        self.reward / visits + EXPLORATION * ((self.availability as f64).ln() / visits).sqrt()
+    }
+}
+
+/// Valid actions worth considering, giving up only when there is nothing else to do
+fn candidate_actions(state: &GameState) -> Vec<Action> {
+    let (_, actions) = state.valid_actions();
+    let plays = actions
+        .iter()
+        .filter(|a| **a != Action::GiveUp)
+        .cloned()
+        .collect_vec();
+    if plays.is_empty() {
+        actions
+    } else {
+        plays
+    }
+}
+
+fn apply(state: &mut GameState, action: &Action, log: &mut Vec<EventEntry>) {
+    let (_, actions) = state.valid_actions();
+    if let Some(action_id) = actions.iter().position(|a| a == action) {
+        _ = state.handle_action(action_id, log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        card_counting_computer::Knowledge,
+        ismcts_computer::{IsmctsComputer, SearchBudget},
+        player::Player,
+    };
+    use royals_core::{
+        card::Card,
+        events::{Action, Event, Play},
+    };
+    use std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    };
+
+    fn guard(opponent: usize, guess: Card) -> Action {
+        Action::Play(Play {
+            card: Card::Guard,
+            opponent: Some(opponent),
+            second_opponent: None,
+            guess: Some(guess),
+        })
+    }
+
+    #[test]
+    fn search_budget_should_parse_iterations_and_time() {
+        assert_eq!("500".parse(), Ok(SearchBudget::Iterations(500)));
+        assert_eq!(
+            "50ms".parse(),
+            Ok(SearchBudget::Time(Duration::from_millis(50)))
+        );
+        assert!("fast".parse::<SearchBudget>().is_err());
+    }
+
+    #[test]
+    fn obtain_action_should_guess_learned_card() {
+        let computer = IsmctsComputer::with_budget(SearchBudget::Iterations(2000));
+        let names = ["A".to_string(), "B".to_string()];
+        let players = names.iter().collect::<Vec<_>>();
+        let game_log = vec![
+            Event::RoundStarted(1, 0, 0),
+            Event::BurnFaceDown(None),
+            Event::BurnFaceUp(Card::Maid),
+            Event::BurnFaceUp(Card::Guard),
+            Event::BurnFaceUp(Card::Prince),
+            Event::PickUp(0, Some(Card::Priest), 11),
+            Event::PickUp(1, None, 10),
+            Event::PickUp(0, Some(Card::Guard), 9),
+            Event::Play(
+                0,
+                Play {
+                    card: Card::Priest,
+                    opponent: Some(1),
+                    second_opponent: None,
+                    guess: None,
+                },
+            ),
+            Event::LearnedCard(1, Some(Card::Baron)),
+            Event::PickUp(1, None, 8),
+            Event::Play(
+                1,
+                Play {
+                    card: Card::Guard,
+                    opponent: Some(0),
+                    second_opponent: None,
+                    guess: Some(Card::King),
+                },
+            ),
+            Event::PickUp(0, Some(Card::Guard), 7),
+        ];
+        let actions = Card::guessable()
+            .iter()
+            .map(|&guess| guard(1, guess))
+            .collect::<Vec<_>>();
+
+        let chosen = computer.obtain_action(&players, &game_log, &actions);
+
+        assert_eq!(actions[chosen], guard(1, Card::Baron));
+    }
+
+    #[test]
+    fn determinize_should_drop_samples_without_burned_card() {
+        let computer = IsmctsComputer::with_budget(SearchBudget::Iterations(10));
+        let mut knowledge = Knowledge {
+            me: 0,
+            hand: vec![Card::Prince, Card::Guard],
+            unseen: HashMap::new(),
+            known: HashMap::from([(1, Card::Baron)]),
+            protected: HashSet::new(),
+            dropped: HashSet::new(),
+            discarded: HashMap::new(),
+            remaining: 0,
+            sycophant_target: None,
+            jester_bets: vec![],
+        };
+        let mut rng = rand::thread_rng();
+
+        assert!(computer.determinize(&knowledge, 2, &mut rng).is_none());
+        assert!(computer.search(&knowledge, 2).is_none());
+
+        knowledge.unseen.insert(Card::Maid, 1);
+
+        let state = computer.determinize(&knowledge, 2, &mut rng).unwrap();
+        assert_eq!(state.burned_card, Some(Card::Maid));
+    }
+}
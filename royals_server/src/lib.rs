@@ -0,0 +1,16 @@
+pub mod auth;
+pub mod card_counting_computer;
+pub mod config;
+pub mod game_lobby;
+pub mod game_logic;
+pub mod game_record;
+pub mod ismcts_computer;
+pub mod message_stats;
+pub mod player;
+pub mod random_playing_computer;
+pub mod remote_player;
+pub mod room;
+pub mod rule_set;
+pub mod sessions;
+pub mod simulation;
+pub mod utils;
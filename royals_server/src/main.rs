@@ -1,114 +1,127 @@
-mod game_lobby;
-mod game_logic;
-mod player;
-mod random_playing_computer;
-mod remote_player;
-mod utils;
-
-use crate::{
-    player::Player, random_playing_computer::RandomPlayingComputer, remote_player::RemotePlayer,
-};
-use game_lobby::GameLobby;
 use renet::{
     transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
     ClientId, ConnectionConfig, DefaultChannel, RenetServer, ServerEvent,
 };
 use royals_core::{
-    events::{ClientEvent, GameEvent},
+    events::ClientEvent,
+    protocol::{
+        decode, encode, Codec, Message, ProtocolError, ServerMessage, PROTOCOL_ID, PROTOCOL_VERSION,
+    },
     user_name::Username,
 };
-use serde::{Deserialize, Serialize};
+use royals_server::{
+    auth::load_private_key,
+    config::{AuthMode, Command, Config, USAGE},
+    game_logic::GameState,
+    game_record::GameRecord,
+    message_stats::{MessageStats, MAX_SLICES},
+    sessions::{SessionEvent, Sessions},
+};
 use std::{
     collections::HashMap,
-    net::{SocketAddr, UdpSocket},
+    net::UdpSocket,
+    path::Path,
     time::{Duration, Instant, SystemTime},
 };
-use tokio::{
-    sync::mpsc::{channel, Receiver, Sender},
-    time,
-};
+use tokio::{sync::mpsc::channel, time};
 
-#[derive(Deserialize, Serialize)]
-struct ServerEvent2 {
-    id: ClientId,
-    event: GameEvent,
-}
+/// How often the sizes of the messages sent are reported
+const STATS_INTERVAL: Duration = Duration::from_secs(600);
 
-pub fn run_game<C, T>(player_constructor: C)
-where
-    C: FnOnce() -> T,
-    T: Player + 'static,
-{
-    let mut lobby = GameLobby::new();
-    lobby.add_player(player_constructor);
-    lobby.add_player(RandomPlayingComputer::new);
-    lobby.add_player(RandomPlayingComputer::new);
-    lobby.add_player(RandomPlayingComputer::new);
-    lobby.play_round();
-}
+/// Malformed messages in a row a client is dropped after, a single one may be a glitch
+const MAX_MALFORMED: usize = 3;
 
-async fn game_loop(
-    name: String,
-    sender: std::sync::mpsc::Sender<GameEvent>,
-    receiver: std::sync::mpsc::Receiver<usize>,
-) {
-    run_game(move || RemotePlayer::new(name, sender, receiver));
+/// Sends the message and returns its size
+fn send(
+    server: &mut RenetServer,
+    client_id: ClientId,
+    codec: Codec,
+    message: &ServerMessage,
+) -> usize {
+    let bytes = encode(codec, message);
+    let size = bytes.len();
+    server.send_message(client_id, DefaultChannel::ReliableOrdered, bytes);
+    size
 }
 
-async fn serve(
-    username: Username,
-    id: ClientId,
-    mut client_event: Receiver<ClientEvent>,
-    server_event: Sender<ServerEvent2>,
-) {
-    let (sender1, receiver1) = std::sync::mpsc::channel();
-    let (sender2, receiver2) = std::sync::mpsc::channel();
-    tokio::spawn(game_loop(username.to_str(), sender1, receiver2));
-    let mut interval = time::interval(Duration::from_millis(50));
-    loop {
-        tokio::select! {
-            _ = interval.tick() => {
-                if let Ok(event) = receiver1.try_recv() {
-                    server_event.send(ServerEvent2{event,id}).await.unwrap();
-                }
-            }
-            msg = client_event.recv() => {
-                if let Some(data) = msg {
-                    sender2.send(data.action_id).unwrap();
-                }
+fn replay(path: &Path) {
+    let record = match GameRecord::load(path) {
+        Ok(record) => record,
+        Err(e) => return println!("Could not read game record {}: {}", path.display(), e),
+    };
+    match record.replay() {
+        Ok(game_log) => {
+            for event in GameState::filter_event(&game_log, None) {
+                println!("{:?}", event);
             }
         }
+        Err(e) => println!("Game record can not be replayed: {}", e),
+    }
+}
+
+fn authentication(auth: &AuthMode) -> Result<ServerAuthentication, String> {
+    match auth {
+        AuthMode::Unsecure => Ok(ServerAuthentication::Unsecure),
+        // only clients with a connect token signed with the private key may connect
+        AuthMode::Secure { key_file } => load_private_key(key_file)
+            .map(|private_key| ServerAuthentication::Secure { private_key })
+            .map_err(|e| format!("Could not load private key {}: {}", key_file.display(), e)),
     }
 }
 
+fn exit_with(error: &str) -> ! {
+    println!("{}", error);
+    std::process::exit(1)
+}
+
 #[tokio::main]
 async fn main() {
-    let mut interval = time::interval(Duration::from_millis(50));
     env_logger::init();
-    println!("Usage: [SERVER_PORT]");
-    let args: Vec<String> = std::env::args().collect();
-    let public_addr: SocketAddr = format!("0.0.0.0:{}", args[1]).parse().unwrap();
+    let config: Config = match Command::from_args(std::env::args().skip(1)) {
+        Ok(Command::Serve(config)) => config,
+        Ok(Command::Replay(path)) => return replay(&path),
+        Ok(Command::Help) => return println!("{}", USAGE),
+        Err(e) => exit_with(&format!("{}\n\n{}", e, USAGE)),
+    };
+    let mut interval = time::interval(Duration::from_millis(config.tick_millis));
     let connection_config = ConnectionConfig::default();
     let mut server: RenetServer = RenetServer::new(connection_config);
 
+    let authentication = authentication(&config.auth).unwrap_or_else(|e| exit_with(&e));
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
     let server_config = ServerConfig {
         current_time,
-        max_clients: 64,
-        protocol_id: 0,
-        public_addresses: vec![public_addr],
-        authentication: ServerAuthentication::Unsecure,
+        max_clients: config.max_clients,
+        protocol_id: PROTOCOL_ID,
+        public_addresses: vec![config.public_addr()],
+        authentication,
     };
-    let socket: UdpSocket = UdpSocket::bind(public_addr).unwrap();
+    let socket = UdpSocket::bind(config.bind_addr())
+        .unwrap_or_else(|e| exit_with(&format!("Could not bind to {}: {}", config.bind_addr(), e)));
 
     let mut transport = NetcodeServerTransport::new(server_config, socket).unwrap();
+    println!("Serving on {}", config.bind_addr());
 
-    let mut client_channels: HashMap<ClientId, Sender<ClientEvent>> = HashMap::new();
+    let (event_tx, mut event_rx) = channel::<SessionEvent>(64);
+    let mut sessions = Sessions::new(event_tx, config.seed);
+    sessions.record_to(config.log_dir);
+    sessions.play_by(config.rules);
+    sessions.limit_bots(config.bots);
+    // only connect tokens vouch for the usernames
+    if let AuthMode::Secure { .. } = config.auth {
+        sessions.trust_usernames();
+    }
 
+    // clients join the sessions once they said hello in a protocol version the server speaks
+    let mut handshakes: HashMap<ClientId, String> = HashMap::new();
+    let mut rejected: Vec<ClientId> = vec![];
+    let mut codecs: HashMap<ClientId, Codec> = HashMap::new();
+    let mut malformed: HashMap<ClientId, usize> = HashMap::new();
+    let mut stats = MessageStats::new();
+    let mut last_report = Instant::now();
     let mut last_updated = Instant::now();
-    let (server_event_tx, mut server_event_rx) = channel::<ServerEvent2>(1);
 
     loop {
         let now = Instant::now();
@@ -116,26 +129,36 @@ async fn main() {
         last_updated = now;
 
         server.update(duration);
-        transport.update(duration, &mut server).unwrap();
+        if let Err(e) = transport.update(duration, &mut server) {
+            println!("Transport error: {}", e);
+        }
 
         while let Some(event) = server.get_event() {
             match event {
                 ServerEvent::ClientConnected { client_id } => {
-                    let user_data = transport.user_data(client_id).unwrap();
-                    let username = Username::from_user_data(&user_data);
-                    println!("Client {} connected.", username.to_str());
-                    let (client_event_tx, client_event_rx) = channel::<ClientEvent>(1);
-                    client_channels.insert(client_id, client_event_tx.clone());
-                    tokio::spawn(serve(
-                        username,
-                        client_id,
-                        client_event_rx,
-                        server_event_tx.clone(),
-                    ));
+                    let username = transport
+                        .user_data(client_id)
+                        .ok_or(ProtocolError::InvalidUsername("no user data".to_string()))
+                        .and_then(|user_data| Username::from_user_data(&user_data));
+                    match username {
+                        Ok(username) => {
+                            println!("Client {} connected.", username.to_str());
+                            handshakes.insert(client_id, username.to_str());
+                        }
+                        Err(e) => {
+                            println!("Dropping client {}: {}", client_id, e);
+                            let rejection = Message::Rejected(e.to_string());
+                            send(&mut server, client_id, Codec::Json, &rejection);
+                            rejected.push(client_id);
+                        }
+                    }
                 }
                 ServerEvent::ClientDisconnected { client_id, reason } => {
                     println!("Client {} disconnected: {}", client_id, reason);
-                    if let Some(_client) = client_channels.remove(&client_id) {}
+                    handshakes.remove(&client_id);
+                    codecs.remove(&client_id);
+                    malformed.remove(&client_id);
+                    sessions.disconnect(client_id);
                 }
             }
         }
@@ -144,22 +167,84 @@ async fn main() {
             while let Some(message) =
                 server.receive_message(client_id, DefaultChannel::ReliableOrdered)
             {
-                let text = String::from_utf8(message.into()).unwrap();
-                if let Ok(msg) = serde_json::from_str(&text) {
-                    let client_channel = client_channels.get(&client_id).unwrap();
-                    _ = client_channel.send(msg).await;
+                let greeting = handshakes.contains_key(&client_id);
+                let codec = codecs.get(&client_id).copied().unwrap_or_default();
+                let decoded = decode::<ClientEvent>(codec, &message);
+                if let Err(ProtocolError::Malformed(e)) = &decoded {
+                    let count = malformed.entry(client_id).or_default();
+                    *count += 1;
+                    if *count < MAX_MALFORMED {
+                        println!("Ignoring message of client {}: {}", client_id, e);
+                        continue;
+                    }
+                } else {
+                    malformed.remove(&client_id);
+                }
+                let rejection = match decoded {
+                    Ok((_, Message::Event(event))) if !greeting => sessions
+                        .handle_client_event(client_id, event)
+                        .err()
+                        .map(|e| e.to_string()),
+                    Ok((PROTOCOL_VERSION, Message::Hello(offered))) if greeting => {
+                        let codec = Codec::negotiate(&offered);
+                        send(
+                            &mut server,
+                            client_id,
+                            Codec::Json,
+                            &Message::Welcome(codec),
+                        );
+                        codecs.insert(client_id, codec);
+                        let username = handshakes.remove(&client_id).unwrap_or_default();
+                        sessions.connect(client_id, username);
+                        None
+                    }
+                    Ok((version, Message::Hello(_))) if greeting => {
+                        Some(ProtocolError::VersionMismatch(version).to_string())
+                    }
+                    Err(e) => Some(e.to_string()),
+                    // anything but a hello is ignored until the handshake is done
+                    Ok(_) => None,
+                };
+                // a misbehaving client is told why and dropped, everybody else plays on
+                if let Some(reason) = rejection {
+                    println!("Dropping client {}: {}", client_id, reason);
+                    send(&mut server, client_id, codec, &Message::Rejected(reason));
+                    rejected.push(client_id);
+                    break;
                 }
             }
         }
 
+        while let Ok(event) = event_rx.try_recv() {
+            sessions.handle_event(event);
+        }
+        sessions.update(now);
+        for (client_id, event) in sessions.outbox() {
+            let codec = codecs.get(&client_id).copied().unwrap_or_default();
+            let kind = MessageStats::kind(&event);
+            let size = send(&mut server, client_id, codec, &Message::Event(event));
+            if stats.record(kind, size) {
+                println!(
+                    "{} message for client {} has {} bytes, more than {} slices",
+                    kind, client_id, size, MAX_SLICES
+                );
+            }
+        }
+        if now - last_report >= STATS_INTERVAL && !stats.is_empty() {
+            println!("Sizes of the messages sent:\n{}", stats.report());
+            last_report = now;
+        }
+
         transport.send_packets(&mut server);
+        // the rejection has been sent by now
+        for client_id in rejected.drain(..) {
+            server.disconnect(client_id);
+        }
         tokio::select! {
             _ = interval.tick() => {}
-            data = server_event_rx.recv() => {
-                if let Some(msg) = data {
-                    if let Ok(s) = serde_json::to_string(&msg.event) {
-                        server.send_message(msg.id, DefaultChannel::ReliableOrdered, s);
-                    }
+            data = event_rx.recv() => {
+                if let Some(event) = data {
+                    sessions.handle_event(event);
                 }
             }
         }
@@ -0,0 +1,125 @@
+use royals_core::events::GameEvent;
+use std::collections::BTreeMap;
+
+/// Payload of a single renet packet, larger messages are split into slices
+pub const SLICE_SIZE: usize = 1200;
+
+/// Messages needing more slices are reported right away
+pub const MAX_SLICES: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SizeStats {
+    pub count: usize,
+    pub total_bytes: usize,
+    pub max_bytes: usize,
+}
+
+/// Sizes of the messages sent to the clients by kind of event.
+#[derive(Default)]
+pub struct MessageStats {
+    sizes: BTreeMap<&'static str, SizeStats>,
+}
+
+impl MessageStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn kind(event: &GameEvent) -> &'static str {
+        match event {
+            GameEvent::Notify(_) => "Notify",
+            GameEvent::Update(_) => "Update",
+            GameEvent::ObtainAction(_) => "ObtainAction",
+            GameEvent::RoomList(_) => "RoomList",
+            GameEvent::RoomUpdate(_) => "RoomUpdate",
+            GameEvent::RoomRejected(_) => "RoomRejected",
+            GameEvent::ActionRejected(_) => "ActionRejected",
+            GameEvent::Seated(_) => "Seated",
+        }
+    }
+
+    /// Records the size of an encoded message and returns whether it needs too many slices
+    pub fn record(&mut self, kind: &'static str, bytes: usize) -> bool {
+        let stats = self.sizes.entry(kind).or_default();
+        stats.count += 1;
+        stats.total_bytes += bytes;
+        stats.max_bytes = stats.max_bytes.max(bytes);
+        bytes.div_ceil(SLICE_SIZE) > MAX_SLICES
+    }
+
+    pub fn get(&self, kind: &str) -> Option<SizeStats> {
+        self.sizes.get(kind).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// One line per kind of message with its average and largest size
+    pub fn report(&self) -> String {
+        self.sizes
+            .iter()
+            .map(|(kind, stats)| {
+                format!(
+                    "{}: {} messages, {} bytes on average, {} bytes at most ({} slices)",
+                    kind,
+                    stats.count,
+                    stats.total_bytes / stats.count.max(1),
+                    stats.max_bytes,
+                    stats.max_bytes.div_ceil(SLICE_SIZE)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game_lobby::GameLobby, message_stats::MessageStats,
+        random_playing_computer::RandomPlayingComputer, rule_set::RuleSet,
+    };
+    use royals_core::{
+        events::{GameEvent, NotifyEvent},
+        protocol::{encode, Codec, Message},
+    };
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[test]
+    fn record_should_keep_the_largest_message() {
+        let mut stats = MessageStats::new();
+
+        assert!(!stats.record("Notify", 100));
+        assert!(stats.record("Notify", 100_000));
+
+        let notify = stats.get("Notify").unwrap();
+        assert_eq!(notify.count, 2);
+        assert_eq!(notify.max_bytes, 100_000);
+    }
+
+    #[test]
+    fn bincode_should_shrink_the_log_of_eight_players() {
+        let mut lobby = GameLobby::new(RuleSet::default(), 8);
+        for _ in 0..8 {
+            lobby.add_player(RandomPlayingComputer::new);
+        }
+        let (spectator_tx, mut spectator_rx) = unbounded_channel();
+        lobby.broadcast_to(spectator_tx);
+        lobby.play_match();
+        let mut last_update = None;
+        while let Ok(update) = spectator_rx.try_recv() {
+            last_update = Some(update);
+        }
+        let update = last_update.unwrap();
+        let event = Message::Event(GameEvent::Notify(NotifyEvent {
+            players: update.players,
+            game_log: update.full_log,
+        }));
+
+        let json = encode(Codec::Json, &event).len();
+        let bincode = encode(Codec::Bincode, &event).len();
+
+        assert!(bincode * 2 < json, "{} vs {} bytes", bincode, json);
+    }
+}
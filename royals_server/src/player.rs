@@ -1,4 +1,7 @@
+use async_trait::async_trait;
+use itertools::Itertools;
 use royals_core::events::{Action, Event};
+use std::sync::Arc;
 
 pub struct PlayerData {
     name: String,
@@ -8,6 +11,10 @@ impl PlayerData {
     pub fn new(name: String) -> Self {
         PlayerData { name }
     }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
 }
 
 pub trait Player {
@@ -27,4 +34,176 @@ pub trait Player {
         game_log: &[Event],
         valid_actions: &[Action],
     ) -> usize;
+
+    /// Whether the last action was chosen on behalf of the player as they ran out of time
+    fn timed_out(&self) -> bool {
+        false
+    }
+}
+
+impl<P: Player + ?Sized> Player for Box<P> {
+    fn data(&self) -> &PlayerData {
+        (**self).data()
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        (**self).data_mut()
+    }
+
+    fn notify(&self, game_log: &[Event], players: &[&String]) {
+        (**self).notify(game_log, players)
+    }
+
+    fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        (**self).obtain_action(players, game_log, valid_actions)
+    }
+
+    fn timed_out(&self) -> bool {
+        (**self).timed_out()
+    }
+}
+
+/// A player that can wait for its decisions without blocking the thread driving the match.
+/// Every synchronous player is an asynchronous one that answers right away.
+#[async_trait]
+pub trait AsyncPlayer: Send + Sync {
+    fn data(&self) -> &PlayerData;
+
+    fn data_mut(&mut self) -> &mut PlayerData;
+
+    fn name(&self) -> &String {
+        &self.data().name
+    }
+
+    async fn notify(&self, game_log: &[Event], players: &[&String]);
+
+    async fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize;
+
+    /// Whether the last action was chosen on behalf of the player as they ran out of time
+    fn timed_out(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<P: Player + Send + Sync> AsyncPlayer for P {
+    fn data(&self) -> &PlayerData {
+        Player::data(self)
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        Player::data_mut(self)
+    }
+
+    async fn notify(&self, game_log: &[Event], players: &[&String]) {
+        Player::notify(self, game_log, players)
+    }
+
+    async fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        Player::obtain_action(self, players, game_log, valid_actions)
+    }
+
+    fn timed_out(&self) -> bool {
+        Player::timed_out(self)
+    }
+}
+
+#[async_trait]
+impl AsyncPlayer for Box<dyn AsyncPlayer> {
+    fn data(&self) -> &PlayerData {
+        (**self).data()
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        (**self).data_mut()
+    }
+
+    async fn notify(&self, game_log: &[Event], players: &[&String]) {
+        (**self).notify(game_log, players).await
+    }
+
+    async fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        (**self)
+            .obtain_action(players, game_log, valid_actions)
+            .await
+    }
+
+    fn timed_out(&self) -> bool {
+        (**self).timed_out()
+    }
+}
+
+/// Lets a computer player think on the blocking thread pool of tokio, so that long searches do
+/// not hold up the other games of the server.
+pub struct BlockingPlayer {
+    data: PlayerData,
+    player: Arc<dyn Player + Send + Sync>,
+}
+
+impl BlockingPlayer {
+    pub fn new<P: Player + Send + Sync + 'static>(player: P) -> Self {
+        BlockingPlayer {
+            data: PlayerData::new(player.name().clone()),
+            player: Arc::new(player),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncPlayer for BlockingPlayer {
+    fn data(&self) -> &PlayerData {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut PlayerData {
+        &mut self.data
+    }
+
+    async fn notify(&self, game_log: &[Event], players: &[&String]) {
+        self.player.notify(game_log, players)
+    }
+
+    async fn obtain_action(
+        &self,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        let player = self.player.clone();
+        let players = players.iter().map(|&p| p.clone()).collect_vec();
+        let game_log = game_log.to_vec();
+        let valid_actions = valid_actions.to_vec();
+        let action = tokio::task::spawn_blocking(move || {
+            player.obtain_action(&players.iter().collect_vec(), &game_log, &valid_actions)
+        })
+        .await;
+        match action {
+            Ok(action) => action,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    fn timed_out(&self) -> bool {
+        self.player.timed_out()
+    }
 }
@@ -20,6 +20,12 @@ impl RandomPlayingComputer {
     }
 }
 
+impl Default for RandomPlayingComputer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Player for RandomPlayingComputer {
     fn data(&self) -> &PlayerData {
         &self.data
@@ -1,24 +1,140 @@
-use crate::player::{Player, PlayerData};
-use royals_core::events::{Action, Event, GameEvent, NotifyEvent, ObtainActionEvent};
-use std::sync::mpsc::{Receiver, Sender};
+use crate::{
+    card_counting_computer::CardCountingComputer,
+    player::{AsyncPlayer, Player, PlayerData},
+};
+use async_trait::async_trait;
+use royals_core::events::{
+    Action, ActionError, ActionId, Event, GameEvent, NotifyEvent, ObtainActionEvent, TimeoutAction,
+    Turn,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        Mutex as AsyncMutex,
+    },
+    time::{self, Instant},
+};
+
+/// What the client answers when asked for an action
+#[derive(Clone, Debug, PartialEq)]
+pub enum Answer {
+    /// Index into the valid actions offered in the turn
+    Index(Turn, ActionId),
+    /// The action itself, taken if it is valid in the turn
+    Value(Turn, Action),
+}
+
+impl Answer {
+    fn turn(&self) -> Turn {
+        match self {
+            Answer::Index(turn, _) | Answer::Value(turn, _) => *turn,
+        }
+    }
+
+    /// Index of the answer among the valid actions of the turn
+    fn resolve(self, turn: Turn, valid_actions: &[Action]) -> Result<ActionId, ActionError> {
+        match self {
+            Answer::Index(answer_turn, _) | Answer::Value(answer_turn, _)
+                if answer_turn != turn =>
+            {
+                Err(ActionError::Stale(answer_turn))
+            }
+            Answer::Index(_, action_id) if action_id < valid_actions.len() => Ok(action_id),
+            Answer::Index(_, action_id) => Err(ActionError::Invalid(action_id)),
+            // the valid actions are the ones the rules of the game allow right now
+            Answer::Value(_, action) => valid_actions
+                .iter()
+                .position(|a| *a == action)
+                .ok_or(ActionError::NotValid(action)),
+        }
+    }
+}
 
 pub struct RemotePlayer {
     pub data: PlayerData,
-    pub sender: Sender<GameEvent>,
-    pub receiver: Receiver<usize>,
+    pub sender: UnboundedSender<GameEvent>,
+    receiver: AsyncMutex<UnboundedReceiver<Answer>>,
+    turn_timeout: Option<(Duration, TimeoutAction)>,
+    /// Number of the next question, so that answers to earlier ones can be told apart
+    next_turn: AtomicU64,
+    timed_out: AtomicBool,
+    /// What the client has been told so far, only changes are sent
+    sent: Mutex<NotifyEvent>,
+    /// Plays the turns the player let time out
+    fallback: CardCountingComputer,
 }
 
 impl RemotePlayer {
-    pub fn new(name: String, sender: Sender<GameEvent>, receiver: Receiver<usize>) -> Self {
+    pub fn new(
+        name: String,
+        sender: UnboundedSender<GameEvent>,
+        receiver: UnboundedReceiver<Answer>,
+    ) -> Self {
         RemotePlayer {
             data: PlayerData::new(name),
             sender,
-            receiver,
+            receiver: AsyncMutex::new(receiver),
+            turn_timeout: None,
+            next_turn: AtomicU64::new(0),
+            timed_out: AtomicBool::new(false),
+            sent: Mutex::new(NotifyEvent::default()),
+            fallback: CardCountingComputer::new(),
+        }
+    }
+
+    /// Once the player took longer than `timeout` for a turn, `action` is taken for them.
+    pub fn set_turn_timeout(&mut self, timeout: Duration, action: TimeoutAction) {
+        self.turn_timeout = Some((timeout, action));
+    }
+
+    /// Sends the events the client does not know yet
+    fn sync(&self, game_log: &[Event], players: &[&String]) {
+        let next = NotifyEvent {
+            players: players.iter().map(|&s| s.to_owned()).collect(),
+            game_log: game_log.to_vec(),
+        };
+        let mut sent = self.sent.lock().unwrap();
+        if let Some(event) = sent.update_to(&next) {
+            // nobody listens anymore once the seat has been given up
+            _ = self.sender.send(event);
         }
+        *sent = next;
     }
+
+    fn fallback_action(
+        &self,
+        action: TimeoutAction,
+        players: &[&String],
+        game_log: &[Event],
+        valid_actions: &[Action],
+    ) -> usize {
+        match action {
+            TimeoutAction::BotMove => {
+                Player::obtain_action(&self.fallback, players, game_log, valid_actions)
+            }
+            TimeoutAction::GiveUp => give_up(valid_actions),
+        }
+    }
+}
+
+/// Index of giving up, which is the first action if it is allowed at all
+fn give_up(valid_actions: &[Action]) -> usize {
+    valid_actions
+        .iter()
+        .position(|a| *a == Action::GiveUp)
+        .unwrap_or(0)
 }
 
-impl Player for RemotePlayer {
+#[async_trait]
+impl AsyncPlayer for RemotePlayer {
     fn data(&self) -> &PlayerData {
         &self.data
     }
@@ -27,36 +143,221 @@ impl Player for RemotePlayer {
         &mut self.data
     }
 
-    fn notify(&self, game_log: &[Event], players: &[&String]) {
-        let players = players.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
-        let game_log = game_log.iter().map(|e| e.to_owned()).collect::<Vec<_>>();
-
-        self.sender
-            .send(GameEvent::Notify(NotifyEvent { players, game_log }))
-            .unwrap();
+    async fn notify(&self, game_log: &[Event], players: &[&String]) {
+        self.sync(game_log, players);
     }
 
-    fn obtain_action(
+    async fn obtain_action(
         &self,
         players: &[&String],
         game_log: &[Event],
         valid_actions: &[Action],
     ) -> usize {
-        let players = players.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
-        let game_log = game_log.iter().map(|e| e.to_owned()).collect::<Vec<_>>();
-        let valid_actions = valid_actions
-            .iter()
-            .map(|a| a.to_owned())
+        self.timed_out.store(false, Ordering::Relaxed);
+        let turn = self.next_turn.fetch_add(1, Ordering::Relaxed);
+        let deadline = self
+            .turn_timeout
+            .map(|(timeout, action)| (Instant::now() + timeout, action));
+        // the time left rather than the deadline, the clocks of client and server may differ
+        let question = || {
+            GameEvent::ObtainAction(ObtainActionEvent {
+                turn,
+                valid_actions: valid_actions.to_vec(),
+                time_left: deadline
+                    .map(|(deadline, _)| deadline.saturating_duration_since(Instant::now())),
+            })
+        };
+
+        // answers which arrived after the previous turn was over are too late
+        let mut receiver = self.receiver.lock().await;
+        let mut queued = VecDeque::new();
+        while let Ok(answer) = receiver.try_recv() {
+            if answer.turn() < turn {
+                let error = ActionError::Stale(answer.turn());
+                _ = self.sender.send(GameEvent::ActionRejected(error));
+            } else {
+                queued.push_back(answer);
+            }
+        }
+
+        self.sync(game_log, players);
+        if self.sender.send(question()).is_err() {
+            return give_up(valid_actions);
+        }
+        loop {
+            let answer = match (queued.pop_front(), deadline) {
+                (Some(answer), _) => Some(answer),
+                (None, Some((deadline, action))) => {
+                    match time::timeout_at(deadline, receiver.recv()).await {
+                        Ok(answer) => answer,
+                        Err(_) => {
+                            self.timed_out.store(true, Ordering::Relaxed);
+                            return self.fallback_action(action, players, game_log, valid_actions);
+                        }
+                    }
+                }
+                (None, None) => receiver.recv().await,
+            };
+            // a seat whose player has left gives up
+            let Some(answer) = answer else {
+                return give_up(valid_actions);
+            };
+            let error = match answer.resolve(turn, valid_actions) {
+                Ok(action_id) => return action_id,
+                Err(error) => error,
+            };
+            // the player learns what was wrong and is asked again
+            _ = self.sender.send(GameEvent::ActionRejected(error));
+            _ = self.sender.send(question());
+        }
+    }
+
+    fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        player::AsyncPlayer,
+        remote_player::{Answer, RemotePlayer},
+    };
+    use royals_core::{
+        card::Card,
+        events::{Action, ActionError, Event, GameEvent, LogUpdate, Play, TimeoutAction},
+    };
+    use std::time::Duration;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[tokio::test]
+    async fn notify_should_only_send_new_events() {
+        let (event_tx, mut event_rx) = unbounded_channel();
+        let (_action_tx, action_rx) = unbounded_channel();
+        let player = RemotePlayer::new("Foo".to_string(), event_tx, action_rx);
+        let name = "Foo".to_string();
+        let log = [Event::RoundStarted(1, 0, 0), Event::DropOut(0)];
+
+        player.notify(&log[..1], &[&name]).await;
+        player.notify(&log[..1], &[&name]).await;
+        player.notify(&log, &[&name]).await;
+
+        assert!(matches!(event_rx.try_recv(), Ok(GameEvent::Notify(_))));
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(GameEvent::Update(LogUpdate { seq: 1, events })) if events == [Event::DropOut(0)]
+        ));
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn obtain_action_should_ask_again_after_rejected_actions() {
+        let (event_tx, mut event_rx) = unbounded_channel();
+        let (action_tx, action_rx) = unbounded_channel();
+        let player = RemotePlayer::new("Foo".to_string(), event_tx, action_rx);
+        for answer in [
+            Answer::Index(5, 0),
+            Answer::Index(0, 7),
+            Answer::Index(0, 1),
+        ] {
+            action_tx.send(answer).unwrap();
+        }
+
+        let chosen = player
+            .obtain_action(&[], &[], &[Action::GiveUp, Action::GiveUp])
+            .await;
+
+        assert_eq!(chosen, 1);
+        let rejections = std::iter::from_fn(|| event_rx.try_recv().ok())
+            .filter_map(|e| match e {
+                GameEvent::ActionRejected(error) => Some(error),
+                _ => None,
+            })
             .collect::<Vec<_>>();
+        assert_eq!(rejections, [ActionError::Stale(5), ActionError::Invalid(7)]);
+    }
+
+    #[tokio::test]
+    async fn obtain_action_should_fall_back_once_the_turn_timed_out() {
+        let (event_tx, mut event_rx) = unbounded_channel();
+        let (_action_tx, action_rx) = unbounded_channel();
+        let mut player = RemotePlayer::new("Foo".to_string(), event_tx, action_rx);
+        player.set_turn_timeout(Duration::from_millis(10), TimeoutAction::GiveUp);
+        let actions = [
+            Action::Play(Play {
+                card: Card::Maid,
+                opponent: None,
+                second_opponent: None,
+                guess: None,
+            }),
+            Action::GiveUp,
+        ];
+
+        let chosen = player.obtain_action(&[], &[], &actions).await;
+
+        assert_eq!(chosen, 1);
+        assert!(player.timed_out());
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(GameEvent::ObtainAction(o))
+                if o.time_left.is_some_and(|t| t <= Duration::from_millis(10))
+        ));
+    }
+
+    #[tokio::test]
+    async fn obtain_action_should_take_valid_actions_by_value() {
+        let (event_tx, mut event_rx) = unbounded_channel();
+        let (action_tx, action_rx) = unbounded_channel();
+        let player = RemotePlayer::new("Foo".to_string(), event_tx, action_rx);
+        let maid = Action::Play(Play {
+            card: Card::Maid,
+            opponent: None,
+            second_opponent: None,
+            guess: None,
+        });
+        action_tx.send(Answer::Value(1, maid.clone())).unwrap();
+        action_tx.send(Answer::Value(0, Action::GiveUp)).unwrap();
+        action_tx.send(Answer::Value(0, maid.clone())).unwrap();
+
+        let chosen = player.obtain_action(&[], &[], &[maid]).await;
+
+        assert_eq!(chosen, 0);
+        let rejections = std::iter::from_fn(|| event_rx.try_recv().ok())
+            .filter_map(|e| match e {
+                GameEvent::ActionRejected(error) => Some(error),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejections,
+            [ActionError::Stale(1), ActionError::NotValid(Action::GiveUp)]
+        );
+    }
+
+    #[tokio::test]
+    async fn obtain_action_should_reject_late_answers_before_asking() {
+        let (event_tx, mut event_rx) = unbounded_channel();
+        let (action_tx, action_rx) = unbounded_channel();
+        let mut player = RemotePlayer::new("Foo".to_string(), event_tx, action_rx);
+        player.set_turn_timeout(Duration::from_millis(10), TimeoutAction::GiveUp);
+        let actions = [Action::GiveUp];
+        player.obtain_action(&[], &[], &actions).await;
+        while event_rx.try_recv().is_ok() {}
 
-        self.sender
-            .send(GameEvent::ObtainAction(ObtainActionEvent {
-                players,
-                game_log,
-                valid_actions,
-            }))
-            .unwrap();
+        action_tx.send(Answer::Index(0, 0)).unwrap();
+        action_tx.send(Answer::Index(1, 0)).unwrap();
+        let chosen = player.obtain_action(&[], &[], &actions).await;
 
-        self.receiver.recv().unwrap()
+        assert_eq!(chosen, 0);
+        assert!(!player.timed_out());
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(GameEvent::ActionRejected(ActionError::Stale(0)))
+        ));
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(GameEvent::ObtainAction(o)) if o.turn == 1
+        ));
+        assert!(event_rx.try_recv().is_err());
     }
 }
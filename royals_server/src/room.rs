@@ -0,0 +1,598 @@
+use rand::{seq::SliceRandom, Rng};
+use renet::ClientId;
+use royals_core::events::{BotLevel, RoomInfo, RoomSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const MIN_SEATS: usize = 2;
+pub const MAX_SEATS: usize = 8;
+
+/// Letters and digits which can not be confused with each other when read aloud
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 6;
+
+/// How many and how strong computer players the server seats in a room
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotLimits {
+    pub max_per_room: usize,
+    /// Rooms asking for stronger bots get this level instead
+    pub max_level: BotLevel,
+}
+
+impl Default for BotLimits {
+    fn default() -> Self {
+        BotLimits {
+            max_per_room: MAX_SEATS,
+            max_level: BotLevel::Hard,
+        }
+    }
+}
+
+/// Somebody watching the game in a room without a seat
+pub struct Spectator {
+    pub client: ClientId,
+    pub name: String,
+    /// Sees every card, with the delay configured for the room
+    pub omniscient: bool,
+}
+
+/// A table connected clients gather at before the host starts the game.
+pub struct Room {
+    /// Identifies the room on the server. Public rooms go by their name, private ones by their
+    /// code, each in a space of their own so that neither clashes with nor reveals the other.
+    pub key: String,
+    pub name: String,
+    pub settings: RoomSettings,
+    pub host: ClientId,
+    pub members: Vec<(ClientId, String)>,
+    pub started: bool,
+    /// Code needed to join a private room
+    pub code: Option<String>,
+    pub spectators: Vec<Spectator>,
+    max_bots: usize,
+}
+
+impl Room {
+    /// Number of computer players seated when the game starts
+    pub fn bot_count(&self) -> usize {
+        if self.settings.fill_with_bots {
+            self.settings
+                .seats
+                .saturating_sub(self.members.len())
+                .min(self.max_bots)
+        } else {
+            0
+        }
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            name: self.name.clone(),
+            host: self
+                .members
+                .iter()
+                .find(|(id, _)| *id == self.host)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_default(),
+            players: self.members.iter().map(|(_, name)| name.clone()).collect(),
+            settings: self.settings.clone(),
+            started: self.started,
+            code: self.code.clone(),
+            spectators: self.spectators.iter().map(|s| s.name.clone()).collect(),
+            spectating: false,
+        }
+    }
+
+    /// The room as seen by one of its members or spectators
+    pub fn info_for(&self, client: ClientId) -> RoomInfo {
+        RoomInfo {
+            spectating: self.spectators.iter().any(|s| s.client == client),
+            ..self.info()
+        }
+    }
+
+    fn seat(&mut self, client: ClientId, player_name: &str) -> Result<&Room, String> {
+        if self.started {
+            return Err(format!(
+                "The game in room {} has already started",
+                self.name
+            ));
+        }
+        if self.members.len() >= self.settings.seats {
+            return Err(format!("Room {} is full", self.name));
+        }
+        let seat_name = self.seat_name(player_name);
+        self.members.push((client, seat_name));
+        Ok(self)
+    }
+
+    /// Player names are made unique within the room
+    fn seat_name(&self, name: &str) -> String {
+        let taken = |candidate: &String| self.members.iter().any(|(_, n)| n == candidate);
+        let mut candidate = name.to_string();
+        let mut i = 1;
+        while taken(&candidate) {
+            i += 1;
+            candidate = format!("{} ({})", name, i);
+        }
+        candidate
+    }
+}
+
+fn public_key(name: &str) -> String {
+    format!("public/{}", name)
+}
+
+fn private_key(code: &str) -> String {
+    format!("private/{}", code)
+}
+
+/// All rooms of the server by key.
+#[derive(Default)]
+pub struct Rooms {
+    rooms: BTreeMap<String, Room>,
+    bot_limits: BotLimits,
+}
+
+impl Rooms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies to the rooms created from now on
+    pub fn limit_bots(&mut self, limits: BotLimits) {
+        self.bot_limits = limits;
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Room> {
+        self.rooms.get(key)
+    }
+
+    /// The room the client plays or watches in
+    pub fn room_of(&self, client: ClientId) -> Option<&Room> {
+        self.rooms.values().find(|room| {
+            room.members.iter().any(|(id, _)| *id == client)
+                || room.spectators.iter().any(|s| s.client == client)
+        })
+    }
+
+    /// Public rooms, private ones can only be found with their code
+    pub fn list(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .values()
+            .filter(|room| !room.settings.private)
+            .map(|room| room.info())
+            .collect()
+    }
+
+    fn generate_code(&self, rng: &mut impl Rng) -> String {
+        loop {
+            let code = (0..CODE_LENGTH)
+                .map(|_| *CODE_ALPHABET.choose(rng).unwrap() as char)
+                .collect::<String>();
+            if !self.rooms.contains_key(&private_key(&code)) {
+                return code;
+            }
+        }
+    }
+
+    pub fn create(
+        &mut self,
+        client: ClientId,
+        player_name: &str,
+        name: String,
+        mut settings: RoomSettings,
+    ) -> Result<&Room, String> {
+        if self.room_of(client).is_some() {
+            return Err("You already are in a room".to_string());
+        }
+        if name.trim().is_empty() {
+            return Err("The room needs a name".to_string());
+        }
+        if !settings.private && self.rooms.contains_key(&public_key(&name)) {
+            return Err(format!("Room {} already exists", name));
+        }
+        if !(MIN_SEATS..=MAX_SEATS).contains(&settings.seats) {
+            return Err(format!(
+                "A room has between {} and {} seats",
+                MIN_SEATS, MAX_SEATS
+            ));
+        }
+        if settings.turn_timeout_secs == Some(0) {
+            return Err("A turn has to last at least a second".to_string());
+        }
+        settings.bot_level = settings.bot_level.min(self.bot_limits.max_level);
+        if self.bot_limits.max_per_room == 0 {
+            settings.fill_with_bots = false;
+            settings.bot_takeover = false;
+        }
+        let code = if settings.private {
+            Some(self.generate_code(&mut rand::thread_rng()))
+        } else {
+            None
+        };
+        let key = match &code {
+            Some(code) => private_key(code),
+            None => public_key(&name),
+        };
+        let room = Room {
+            key: key.clone(),
+            name,
+            settings,
+            host: client,
+            members: vec![(client, player_name.to_string())],
+            started: false,
+            code,
+            spectators: vec![],
+            max_bots: self.bot_limits.max_per_room,
+        };
+        self.rooms.insert(key.clone(), room);
+        Ok(&self.rooms[&key])
+    }
+
+    pub fn join(
+        &mut self,
+        client: ClientId,
+        player_name: &str,
+        name: &str,
+    ) -> Result<&Room, String> {
+        if self.room_of(client).is_some() {
+            return Err("You already are in a room".to_string());
+        }
+        let room = self
+            .rooms
+            .get_mut(&public_key(name))
+            .ok_or(format!("Room {} does not exist", name))?;
+        room.seat(client, player_name)
+    }
+
+    pub fn join_with_code(
+        &mut self,
+        client: ClientId,
+        player_name: &str,
+        code: &str,
+    ) -> Result<&Room, String> {
+        if self.room_of(client).is_some() {
+            return Err("You already are in a room".to_string());
+        }
+        let code = code.trim().to_uppercase();
+        let room = self
+            .rooms
+            .get_mut(&private_key(&code))
+            .ok_or(format!("There is no room with code {}", code))?;
+        room.seat(client, player_name)
+    }
+
+    /// Lets the client watch the game in a public room, also once it has started
+    pub fn spectate(
+        &mut self,
+        client: ClientId,
+        name: &str,
+        room: &str,
+        omniscient: bool,
+    ) -> Result<&Room, String> {
+        if self.room_of(client).is_some() {
+            return Err("You already are in a room".to_string());
+        }
+        let room = self
+            .rooms
+            .get_mut(&public_key(room))
+            .ok_or(format!("Room {} does not exist", room))?;
+        if omniscient && room.settings.omniscient_delay_secs.is_none() {
+            return Err(format!("Room {} does not reveal the hands", room.name));
+        }
+        room.spectators.push(Spectator {
+            client,
+            name: name.to_string(),
+            omniscient,
+        });
+        Ok(room)
+    }
+
+    /// Removes the client from its room and returns the key of the room. The host role passes
+    /// on to the next member and empty rooms are closed unless a game is still running.
+    pub fn leave(&mut self, client: ClientId) -> Option<String> {
+        let key = self.room_of(client)?.key.clone();
+        let room = self.rooms.get_mut(&key)?;
+        room.members.retain(|(id, _)| *id != client);
+        room.spectators.retain(|s| s.client != client);
+        if room.host == client {
+            if let Some((next, _)) = room.members.first() {
+                room.host = *next;
+            }
+        }
+        if room.members.is_empty() && !room.started {
+            self.rooms.remove(&key);
+        }
+        Some(key)
+    }
+
+    /// Hands the seat of a client over to its new connection
+    pub fn rejoin(&mut self, old: ClientId, new: ClientId) -> Option<&Room> {
+        let key = self.room_of(old)?.key.clone();
+        let room = self.rooms.get_mut(&key)?;
+        for (id, _) in room.members.iter_mut().filter(|(id, _)| *id == old) {
+            *id = new;
+        }
+        if room.host == old {
+            room.host = new;
+        }
+        Some(room)
+    }
+
+    /// Marks the game of the client's room as started and returns the room
+    pub fn start(&mut self, client: ClientId) -> Result<&Room, String> {
+        let key = self
+            .room_of(client)
+            .ok_or("You are not in a room".to_string())?
+            .key
+            .clone();
+        let room = self.rooms.get_mut(&key).unwrap();
+        if room.host != client {
+            return Err("Only the host can start the game".to_string());
+        }
+        if room.started {
+            return Err("The game has already started".to_string());
+        }
+        if room.members.len() + room.bot_count() < MIN_SEATS {
+            return Err(format!("At least {} players are needed", MIN_SEATS));
+        }
+        room.started = true;
+        Ok(room)
+    }
+
+    /// Opens the room for a new game, closing it if everybody left meanwhile
+    pub fn finish(&mut self, key: &str) -> Option<&Room> {
+        let room = self.rooms.get_mut(key)?;
+        room.started = false;
+        if room.members.is_empty() {
+            self.rooms.remove(key);
+            return None;
+        }
+        self.rooms.get(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::room::{public_key, BotLimits, Rooms};
+    use renet::ClientId;
+    use royals_core::events::{BotLevel, RoomSettings};
+
+    fn client(id: u64) -> ClientId {
+        ClientId::from_raw(id)
+    }
+
+    #[test]
+    fn join_should_seat_players_with_unique_names() {
+        let mut rooms = Rooms::new();
+        rooms
+            .create(
+                client(1),
+                "Foo",
+                "table".to_string(),
+                RoomSettings::default(),
+            )
+            .unwrap();
+
+        let room = rooms.join(client(2), "Foo", "table").unwrap();
+
+        assert_eq!(room.info().players, vec!["Foo", "Foo (2)"]);
+        assert_eq!(room.info().host, "Foo");
+    }
+
+    #[test]
+    fn join_should_reject_full_and_unknown_rooms() {
+        let mut rooms = Rooms::new();
+        let settings = RoomSettings {
+            seats: 2,
+            fill_with_bots: false,
+            ..RoomSettings::default()
+        };
+        rooms
+            .create(client(1), "Foo", "table".to_string(), settings)
+            .unwrap();
+        rooms.join(client(2), "Bar", "table").unwrap();
+
+        assert!(rooms.join(client(3), "Baz", "table").is_err());
+        assert!(rooms.join(client(3), "Baz", "other").is_err());
+    }
+
+    #[test]
+    fn private_rooms_should_only_be_joined_with_their_code() {
+        let mut rooms = Rooms::new();
+        let settings = RoomSettings {
+            private: true,
+            ..RoomSettings::default()
+        };
+        let code = rooms
+            .create(client(1), "Foo", "table".to_string(), settings)
+            .unwrap()
+            .code
+            .clone()
+            .unwrap();
+
+        assert!(rooms.list().is_empty());
+        assert!(rooms.join(client(2), "Bar", "table").is_err());
+        assert!(rooms.join_with_code(client(2), "Bar", "WRONG1").is_err());
+        let room = rooms
+            .join_with_code(client(2), "Bar", &code.to_lowercase())
+            .unwrap();
+        assert_eq!(room.info().players, vec!["Foo", "Bar"]);
+    }
+
+    #[test]
+    fn create_should_not_reveal_private_rooms() {
+        let mut rooms = Rooms::new();
+        let settings = RoomSettings {
+            private: true,
+            ..RoomSettings::default()
+        };
+        rooms
+            .create(client(1), "Foo", "table".to_string(), settings.clone())
+            .unwrap();
+
+        assert!(rooms
+            .create(client(2), "Bar", "table".to_string(), settings)
+            .is_ok());
+        assert!(rooms
+            .create(
+                client(3),
+                "Baz",
+                "table".to_string(),
+                RoomSettings::default()
+            )
+            .is_ok());
+        assert!(rooms.join(client(4), "Qux", "table").is_ok());
+        assert_eq!(rooms.list().len(), 1);
+    }
+
+    #[test]
+    fn create_should_not_reveal_codes_of_private_rooms() {
+        let mut rooms = Rooms::new();
+        let settings = RoomSettings {
+            private: true,
+            ..RoomSettings::default()
+        };
+        let code = rooms
+            .create(client(1), "Foo", "table".to_string(), settings)
+            .unwrap()
+            .code
+            .clone()
+            .unwrap();
+
+        let room = rooms
+            .create(client(2), "Bar", code.clone(), RoomSettings::default())
+            .unwrap();
+
+        assert_eq!(room.name, code);
+        assert_eq!(room.info().players, vec!["Bar"]);
+        assert!(rooms.join(client(3), "Baz", &code).is_ok());
+        let private = rooms.join_with_code(client(4), "Qux", &code).unwrap();
+        assert_eq!(private.info().players, vec!["Foo", "Qux"]);
+    }
+
+    #[test]
+    fn start_should_only_be_allowed_for_the_host() {
+        let mut rooms = Rooms::new();
+        rooms
+            .create(
+                client(1),
+                "Foo",
+                "table".to_string(),
+                RoomSettings::default(),
+            )
+            .unwrap();
+        rooms.join(client(2), "Bar", "table").unwrap();
+
+        assert!(rooms.start(client(2)).is_err());
+        let room = rooms.start(client(1)).unwrap();
+
+        assert_eq!(room.bot_count(), 2);
+        assert!(rooms.join(client(3), "Baz", "table").is_err());
+    }
+
+    #[test]
+    fn create_should_keep_bots_within_the_limits() {
+        let mut rooms = Rooms::new();
+        rooms.limit_bots(BotLimits {
+            max_per_room: 1,
+            max_level: BotLevel::Medium,
+        });
+        let settings = RoomSettings {
+            bot_level: BotLevel::Hard,
+            ..RoomSettings::default()
+        };
+
+        let room = rooms
+            .create(client(1), "Foo", "table".to_string(), settings)
+            .unwrap();
+
+        assert_eq!(room.bot_count(), 1);
+        assert_eq!(room.settings.bot_level, BotLevel::Medium);
+    }
+
+    #[test]
+    fn create_should_reject_turns_without_time() {
+        let mut rooms = Rooms::new();
+        let settings = RoomSettings {
+            turn_timeout_secs: Some(0),
+            ..RoomSettings::default()
+        };
+
+        assert!(rooms
+            .create(client(1), "Foo", "table".to_string(), settings)
+            .is_err());
+        assert!(rooms.room_of(client(1)).is_none());
+    }
+
+    #[test]
+    fn rejoin_should_keep_seat_and_host() {
+        let mut rooms = Rooms::new();
+        rooms
+            .create(
+                client(1),
+                "Foo",
+                "table".to_string(),
+                RoomSettings::default(),
+            )
+            .unwrap();
+        rooms.join(client(2), "Bar", "table").unwrap();
+
+        let room = rooms.rejoin(client(1), client(3)).unwrap();
+
+        assert_eq!(room.host, client(3));
+        assert_eq!(room.info().players, vec!["Foo", "Bar"]);
+        assert!(rooms.room_of(client(1)).is_none());
+    }
+
+    #[test]
+    fn leave_should_pass_on_the_host_and_close_empty_rooms() {
+        let mut rooms = Rooms::new();
+        rooms
+            .create(
+                client(1),
+                "Foo",
+                "table".to_string(),
+                RoomSettings::default(),
+            )
+            .unwrap();
+        rooms.join(client(2), "Bar", "table").unwrap();
+
+        rooms.leave(client(1));
+        assert_eq!(rooms.get(&public_key("table")).unwrap().host, client(2));
+
+        rooms.leave(client(2));
+        assert!(rooms.get(&public_key("table")).is_none());
+    }
+
+    #[test]
+    fn spectate_should_watch_started_games_without_a_seat() {
+        let mut rooms = Rooms::new();
+        rooms
+            .create(
+                client(1),
+                "Foo",
+                "table".to_string(),
+                RoomSettings::default(),
+            )
+            .unwrap();
+        rooms.start(client(1)).unwrap();
+
+        assert!(rooms.spectate(client(2), "Bar", "table", true).is_err());
+        let room = rooms.spectate(client(2), "Bar", "table", false).unwrap();
+
+        assert_eq!(room.info().players, vec!["Foo"]);
+        assert_eq!(room.info().spectators, vec!["Bar"]);
+        assert!(room.info_for(client(2)).spectating);
+        assert!(!room.info_for(client(1)).spectating);
+        assert!(rooms.start(client(2)).is_err());
+        rooms.leave(client(2));
+        assert!(rooms
+            .get(&public_key("table"))
+            .unwrap()
+            .spectators
+            .is_empty());
+    }
+}
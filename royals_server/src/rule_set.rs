@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// House rules a table can agree on. The default follows the rules this game has always used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSet {
+    /// Attacking a protected player lets the attacker drop out, otherwise it is not allowed.
+    pub drop_out_on_protected_attack: bool,
+    /// Playing the princess is allowed and is equivalent to giving up.
+    pub princess_playable: bool,
+    /// Players holding equally high cards at the end of the round share the win, otherwise
+    /// nobody wins the round.
+    pub shared_win_on_tie: bool,
+    /// Ties are broken by the total value of the folded cards before the win is shared.
+    pub tie_break_by_discards: bool,
+    /// Giving up is always a valid action.
+    pub give_up_allowed: bool,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            drop_out_on_protected_attack: true,
+            princess_playable: true,
+            shared_win_on_tie: true,
+            tie_break_by_discards: true,
+            give_up_allowed: true,
+        }
+    }
+}
@@ -0,0 +1,1082 @@
+use crate::{
+    card_counting_computer::CardCountingComputer,
+    game_lobby::{GameLobby, SeatSwap, SpectatorUpdate, SwapReason},
+    ismcts_computer::IsmctsComputer,
+    player::{AsyncPlayer, BlockingPlayer},
+    random_playing_computer::RandomPlayingComputer,
+    remote_player::{Answer, RemotePlayer},
+    room::{BotLimits, Rooms},
+    rule_set::RuleSet,
+};
+use itertools::Itertools;
+use rand::{distributions::Alphanumeric, Rng};
+use renet::ClientId;
+use royals_core::events::{
+    ActionError, BotLevel, ClientEvent, GameEvent, NotifyEvent, RoomSettings,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    iter::once,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, Sender, UnboundedReceiver, UnboundedSender},
+    task::JoinError,
+};
+
+/// How long the seat of a disconnected player is kept for them to reconnect
+pub const RECONNECT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+pub type SeatId = u64;
+
+/// Events of the running games, to be passed back in by the network loop
+pub enum SessionEvent {
+    /// Game event for the human at a seat, whichever connection they currently use
+    Seat(SeatId, GameEvent),
+    /// Latest view of the game in the room for its spectators
+    Spectators(String, SpectatorUpdate),
+    GameFinished(String, Result<(), GameError>),
+}
+
+/// Why the event of a client is refused. Clients sending such events are dropped.
+#[derive(Debug, PartialEq)]
+pub enum SessionError {
+    /// The client did not finish the handshake or is gone already
+    UnknownClient(ClientId),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownClient(client_id) => write!(f, "Unknown client {}", client_id),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Why a game ended before it was decided
+#[derive(Debug, PartialEq)]
+pub enum GameError {
+    Panicked(String),
+    Cancelled,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::Panicked(message) => write!(f, "The game crashed: {}", message),
+            GameError::Cancelled => write!(f, "The game was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<JoinError> for GameError {
+    fn from(e: JoinError) -> Self {
+        if !e.is_panic() {
+            return GameError::Cancelled;
+        }
+        let panic = e.into_panic();
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        GameError::Panicked(message)
+    }
+}
+
+/// A connected client and the seat it plays at
+struct Client {
+    name: String,
+    seat: Option<SeatId>,
+}
+
+/// The place of a human in a running game, which outlives the connection of its player.
+struct Seat {
+    room: String,
+    /// Name of the player in the game
+    name: String,
+    username: String,
+    /// Handed to the player, who reclaims the seat with it after losing the connection
+    token: String,
+    /// Id of the client holding the seat in the room
+    member: ClientId,
+    actions: UnboundedSender<Answer>,
+    /// Everything the player was told so far, sent as a snapshot when the player reconnects
+    snapshot: NotifyEvent,
+    pending_action: Option<GameEvent>,
+    /// When the pending action was asked for, to tell a reconnecting player the time left
+    asked_at: Instant,
+    disconnected_since: Option<Instant>,
+    /// A computer player plays in place of the human
+    taken_over: bool,
+}
+
+/// A running game and the channel to swap its seats
+struct Game {
+    settings: RoomSettings,
+    swaps: UnboundedSender<SeatSwap>,
+    /// Views told to the spectators so far, sent as snapshots to spectators joining in
+    public: NotifyEvent,
+    omniscient: NotifyEvent,
+    /// The full view already queued for the omniscient spectators
+    full: NotifyEvent,
+}
+
+/// Connected clients, the rooms they gather in and the seats of the running games. Messages for
+/// the clients are collected in an outbox which the network loop sends.
+pub struct Sessions {
+    rooms: Rooms,
+    clients: HashMap<ClientId, Client>,
+    seats: HashMap<SeatId, Seat>,
+    next_seat: SeatId,
+    games: HashMap<String, Game>,
+    events: Sender<SessionEvent>,
+    /// Views revealing every hand by room, held back until they are due. They outlive the game
+    /// so that the end of the match is shown as well.
+    delayed_views: Vec<(Instant, String, GameEvent)>,
+    seed: Option<u64>,
+    rules: RuleSet,
+    record_dir: Option<PathBuf>,
+    /// Number of games started, which is added to the configured seed so every game is dealt
+    /// differently
+    games_started: u64,
+    /// The usernames are vouched for by connect tokens, so players may reclaim their seat by name
+    trusted_usernames: bool,
+    outbox: Vec<(ClientId, GameEvent)>,
+}
+
+impl Sessions {
+    /// Events of the running games are sent to `events`.
+    pub fn new(events: Sender<SessionEvent>, seed: Option<u64>) -> Self {
+        Sessions {
+            rooms: Rooms::new(),
+            clients: HashMap::new(),
+            seats: HashMap::new(),
+            next_seat: 0,
+            games: HashMap::new(),
+            events,
+            delayed_views: vec![],
+            seed,
+            rules: RuleSet::default(),
+            record_dir: None,
+            games_started: 0,
+            trusted_usernames: false,
+            outbox: vec![],
+        }
+    }
+
+    /// Records of all games are written to the given directory.
+    pub fn record_to(&mut self, dir: PathBuf) {
+        self.record_dir = Some(dir);
+    }
+
+    /// All games are played by the given house rules.
+    pub fn play_by(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    /// Lets players reconnecting with the username of a seat take it again. Only safe if nobody
+    /// can choose the username of somebody else.
+    pub fn trust_usernames(&mut self) {
+        self.trusted_usernames = true;
+    }
+
+    /// Limits the computer players of the rooms created from now on.
+    pub fn limit_bots(&mut self, limits: BotLimits) {
+        self.rooms.limit_bots(limits);
+    }
+
+    /// Takes the messages to be sent to the clients
+    pub fn outbox(&mut self) -> Vec<(ClientId, GameEvent)> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn send(&mut self, client_id: ClientId, event: GameEvent) {
+        self.outbox.push((client_id, event));
+    }
+
+    /// Sends the room to its members and the list of rooms to everybody else
+    fn broadcast_rooms(&mut self) {
+        let room_list = GameEvent::RoomList(self.rooms.list());
+        for &client_id in self.clients.keys() {
+            let event = match self.rooms.room_of(client_id) {
+                Some(room) => GameEvent::RoomUpdate(room.info_for(client_id)),
+                None => room_list.clone(),
+            };
+            self.outbox.push((client_id, event));
+        }
+    }
+
+    /// A player coming back within the grace period takes their seat again, if their username
+    /// can be trusted. Everybody else reclaims it with the token of the seat.
+    pub fn connect(&mut self, client_id: ClientId, username: String) {
+        let seat = self
+            .seats
+            .iter()
+            .filter(|_| self.trusted_usernames)
+            .find(|(_, seat)| seat.username == username && seat.disconnected_since.is_some())
+            .map(|(&seat_id, _)| seat_id);
+        self.clients.insert(
+            client_id,
+            Client {
+                name: username,
+                seat: None,
+            },
+        );
+        match seat {
+            Some(seat_id) => self.reattach(client_id, seat_id),
+            None => self.send(client_id, GameEvent::RoomList(self.rooms.list())),
+        }
+    }
+
+    /// The client takes the seat of the token, from a connection the server did not notice to be
+    /// lost yet as well
+    fn reclaim(&mut self, client_id: ClientId, token: &str) -> Result<(), String> {
+        if self.rooms.room_of(client_id).is_some() {
+            return Err("You already are in a room".to_string());
+        }
+        let Some((&seat_id, seat)) = self.seats.iter().find(|(_, seat)| seat.token == token) else {
+            return Err("There is no seat to reclaim".to_string());
+        };
+        let old = seat.member;
+        self.reattach(client_id, seat_id);
+        if let Some(client) = self.clients.get_mut(&old) {
+            client.seat = None;
+            self.send(old, GameEvent::RoomList(self.rooms.list()));
+        }
+        Ok(())
+    }
+
+    fn reattach(&mut self, client_id: ClientId, seat_id: SeatId) {
+        let Some(seat) = self.seats.get_mut(&seat_id) else {
+            return;
+        };
+        self.rooms.rejoin(seat.member, client_id);
+        seat.member = client_id;
+        seat.disconnected_since = None;
+        if seat.taken_over {
+            self.return_seat(seat_id);
+        }
+        let Some(seat) = self.seats.get(&seat_id) else {
+            return;
+        };
+        // the token for the next time, a snapshot of the log, then the question the game is
+        // waiting for
+        let snapshot =
+            (!seat.snapshot.game_log.is_empty()).then(|| GameEvent::Notify(seat.snapshot.clone()));
+        let resync = once(GameEvent::Seated(seat.token.clone()))
+            .chain(snapshot)
+            .chain(seat.pending_action.clone().map(|event| match event {
+                GameEvent::ObtainAction(mut question) => {
+                    let elapsed = seat.asked_at.elapsed();
+                    question.time_left = question.time_left.map(|t| t.saturating_sub(elapsed));
+                    GameEvent::ObtainAction(question)
+                }
+                event => event,
+            }))
+            .collect_vec();
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            client.seat = Some(seat_id);
+        }
+        self.broadcast_rooms();
+        for event in resync {
+            self.send(client_id, event);
+        }
+    }
+
+    /// Players in a running game keep their seat for the grace period, everybody else leaves
+    /// their room.
+    pub fn disconnect(&mut self, client_id: ClientId) {
+        let Some(client) = self.clients.remove(&client_id) else {
+            return;
+        };
+        match client.seat.and_then(|seat_id| self.seats.get_mut(&seat_id)) {
+            Some(seat) => {
+                seat.disconnected_since = Some(Instant::now());
+                self.hand_over(client.seat.unwrap());
+            }
+            None => {
+                if self.rooms.leave(client_id).is_some() {
+                    self.broadcast_rooms();
+                }
+            }
+        }
+    }
+
+    /// Lets a computer player take over the seat if the room allows it
+    fn hand_over(&mut self, seat_id: SeatId) {
+        let Some(seat) = self.seats.get_mut(&seat_id) else {
+            return;
+        };
+        let Some(game) = self.games.get(&seat.room) else {
+            return;
+        };
+        if !game.settings.bot_takeover || seat.taken_over {
+            return;
+        }
+        let swap = SeatSwap {
+            name: seat.name.clone(),
+            player: computer_player(game.settings.bot_level, &self.rules),
+            reason: SwapReason::Takeover,
+        };
+        if game.swaps.send(swap).is_ok() {
+            seat.taken_over = true;
+            seat.pending_action = None;
+            // closing the channel ends the turn the game may be waiting for
+            seat.actions = unbounded_channel().0;
+        }
+    }
+
+    /// Seats the returning player again at the start of the next round
+    fn return_seat(&mut self, seat_id: SeatId) {
+        let Some(seat) = self.seats.get(&seat_id) else {
+            return;
+        };
+        let (room, name) = (seat.room.clone(), seat.name.clone());
+        let Some(settings) = self.games.get(&room).map(|game| game.settings.clone()) else {
+            return;
+        };
+        let player = self.remote_player(seat_id, name.clone(), &settings);
+        let swap = SeatSwap {
+            name,
+            player: Box::new(player),
+            reason: SwapReason::Return,
+        };
+        if self.games[&room].swaps.send(swap).is_ok() {
+            if let Some(seat) = self.seats.get_mut(&seat_id) {
+                seat.taken_over = false;
+            }
+        }
+    }
+
+    /// Releases what is due by now, the seats of players who did not reconnect in time and the
+    /// delayed views of omniscient spectators
+    pub fn update(&mut self, now: Instant) {
+        self.release_expired_seats(now);
+        self.release_delayed_views(now);
+    }
+
+    /// Gives up the seats of players who did not reconnect in time
+    fn release_expired_seats(&mut self, now: Instant) {
+        let expired = self
+            .seats
+            .iter()
+            .filter(|(_, seat)| {
+                seat.disconnected_since
+                    .map_or(false, |since| now - since >= RECONNECT_GRACE_PERIOD)
+            })
+            .map(|(&seat_id, _)| seat_id)
+            .collect_vec();
+        if expired.is_empty() {
+            return;
+        }
+        for seat_id in expired {
+            self.release_seat(seat_id);
+        }
+        self.broadcast_rooms();
+    }
+
+    /// The player leaves the game, a computer player or giving up takes their place
+    fn release_seat(&mut self, seat_id: SeatId) {
+        self.hand_over(seat_id);
+        if let Some(seat) = self.seats.remove(&seat_id) {
+            self.rooms.leave(seat.member);
+            if let Some(client) = self.clients.get_mut(&seat.member) {
+                client.seat = None;
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::Seat(seat_id, event) => self.handle_seat_event(seat_id, event),
+            SessionEvent::Spectators(room, update) => self.handle_spectator_update(&room, update),
+            SessionEvent::GameFinished(room, result) => self.finish_game(&room, result),
+        }
+    }
+
+    fn release_delayed_views(&mut self, now: Instant) {
+        let (due, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed_views)
+            .into_iter()
+            .partition(|(due, _, _)| *due <= now);
+        self.delayed_views = delayed;
+        for (_, room, event) in due {
+            if let Some(game) = self.games.get_mut(&room) {
+                game.omniscient.apply(&event);
+            }
+            self.send_to_spectators(&room, true, event);
+        }
+    }
+
+    fn handle_spectator_update(&mut self, room: &str, update: SpectatorUpdate) {
+        let Some(game) = self.games.get_mut(room) else {
+            return;
+        };
+        let public = NotifyEvent {
+            players: update.players.clone(),
+            game_log: update.public_log,
+        };
+        let public_update = game.public.update_to(&public);
+        game.public = public;
+        if let Some(secs) = game.settings.omniscient_delay_secs {
+            let full = NotifyEvent {
+                players: update.players,
+                game_log: update.full_log,
+            };
+            if let Some(full_update) = game.full.update_to(&full) {
+                self.delayed_views.push((
+                    Instant::now() + Duration::from_secs(secs),
+                    room.to_string(),
+                    full_update,
+                ));
+            }
+            game.full = full;
+        }
+        if let Some(event) = public_update {
+            self.send_to_spectators(room, false, event);
+        }
+    }
+
+    fn send_to_spectators(&mut self, room: &str, omniscient: bool, event: GameEvent) {
+        let Some(room) = self.rooms.get(room) else {
+            return;
+        };
+        let spectators = room
+            .spectators
+            .iter()
+            .filter(|s| s.omniscient == omniscient)
+            .map(|s| s.client)
+            .collect_vec();
+        for client_id in spectators {
+            self.send(client_id, event.clone());
+        }
+    }
+
+    /// Sends the latest view of the running game to a new spectator
+    fn catch_up(&mut self, client_id: ClientId, room: &str, omniscient: bool) {
+        let Some(game) = self.games.get(room) else {
+            return;
+        };
+        let view = match omniscient {
+            true => &game.omniscient,
+            false => &game.public,
+        };
+        if !view.game_log.is_empty() {
+            self.send(client_id, GameEvent::Notify(view.clone()));
+        }
+    }
+
+    fn handle_seat_event(&mut self, seat: SeatId, event: GameEvent) {
+        let Some(seat) = self.seats.get_mut(&seat) else {
+            return;
+        };
+        match event {
+            GameEvent::ObtainAction(_) => {
+                seat.pending_action = Some(event.clone());
+                seat.asked_at = Instant::now();
+            }
+            GameEvent::Notify(_) | GameEvent::Update(_) => {
+                // every action is followed by a notification, so nothing is pending anymore
+                seat.snapshot.apply(&event);
+                seat.pending_action = None;
+            }
+            _ => {}
+        }
+        if seat.disconnected_since.is_none() {
+            let member = seat.member;
+            self.send(member, event);
+        }
+    }
+
+    /// Passes the answer on to the player at the seat, which checks it and asks again if it is
+    /// not valid
+    fn answer(&mut self, client_id: ClientId, seat: Option<SeatId>, answer: Answer) {
+        let error = match seat.and_then(|seat_id| self.seats.get_mut(&seat_id)) {
+            // the question stays pending until the game moved on, an invalid answer may be
+            // followed by a valid one
+            Some(seat) if seat.pending_action.is_some() => {
+                let sent = seat.actions.send(answer);
+                sent.err().map(|_| ActionError::NotYourTurn)
+            }
+            // spectators have no seat, and the turn may have timed out already
+            _ => Some(ActionError::NotYourTurn),
+        };
+        if let Some(error) = error {
+            self.send(client_id, GameEvent::ActionRejected(error));
+        }
+    }
+
+    pub fn handle_client_event(
+        &mut self,
+        client_id: ClientId,
+        event: ClientEvent,
+    ) -> Result<(), SessionError> {
+        let Some(client) = self.clients.get(&client_id) else {
+            return Err(SessionError::UnknownClient(client_id));
+        };
+        let name = client.name.clone();
+        let seat = client.seat;
+        let result = match event {
+            ClientEvent::Action(turn, action_id) => {
+                self.answer(client_id, seat, Answer::Index(turn, action_id));
+                return Ok(());
+            }
+            ClientEvent::Submit(turn, action) => {
+                self.answer(client_id, seat, Answer::Value(turn, action));
+                return Ok(());
+            }
+            ClientEvent::Reclaim(token) => self.reclaim(client_id, &token),
+            ClientEvent::ListRooms => {
+                self.send(client_id, GameEvent::RoomList(self.rooms.list()));
+                return Ok(());
+            }
+            ClientEvent::CreateRoom(room, settings) => self
+                .rooms
+                .create(client_id, &name, room, settings)
+                .map(|_| ()),
+            ClientEvent::JoinRoom(room) => self.rooms.join(client_id, &name, &room).map(|_| ()),
+            ClientEvent::JoinWithCode(code) => self
+                .rooms
+                .join_with_code(client_id, &name, &code)
+                .map(|_| ()),
+            ClientEvent::Spectate(room, omniscient) => {
+                match self.rooms.spectate(client_id, &name, &room, omniscient) {
+                    Ok(room) => {
+                        let key = room.key.clone();
+                        self.catch_up(client_id, &key, omniscient);
+                        Ok(())
+                    }
+                    Err(reason) => Err(reason),
+                }
+            }
+            ClientEvent::LeaveRoom => {
+                match seat {
+                    Some(seat_id) => self.release_seat(seat_id),
+                    None => _ = self.rooms.leave(client_id),
+                }
+                Ok(())
+            }
+            ClientEvent::StartGame => self
+                .rooms
+                .start(client_id)
+                .map(|room| room.key.clone())
+                .map(|room| self.start_game(&room)),
+        };
+        // a room refusing the client is nothing the client did wrong
+        match result {
+            Ok(()) => self.broadcast_rooms(),
+            Err(reason) => self.send(client_id, GameEvent::RoomRejected(reason)),
+        }
+        Ok(())
+    }
+
+    /// Opens the room for the next game, players who did not come back leave it. Everybody in the
+    /// room is told if the game was aborted.
+    fn finish_game(&mut self, room: &str, result: Result<(), GameError>) {
+        if let Err(e) = result {
+            println!("Game in room {} was aborted: {}", room, e);
+            if let Some(room) = self.rooms.get(room) {
+                let clients = room
+                    .members
+                    .iter()
+                    .map(|(client_id, _)| *client_id)
+                    .chain(room.spectators.iter().map(|s| s.client))
+                    .collect_vec();
+                for client_id in clients {
+                    self.send(client_id, GameEvent::RoomRejected(e.to_string()));
+                }
+            }
+        }
+        let seats = self
+            .seats
+            .iter()
+            .filter(|(_, seat)| seat.room == room)
+            .map(|(&seat_id, _)| seat_id)
+            .collect_vec();
+        for seat_id in seats {
+            let Some(seat) = self.seats.remove(&seat_id) else {
+                continue;
+            };
+            match self.clients.get_mut(&seat.member) {
+                Some(client) => client.seat = None,
+                None => _ = self.rooms.leave(seat.member),
+            }
+        }
+        self.games.remove(room);
+        self.rooms.finish(room);
+        self.broadcast_rooms();
+    }
+
+    /// Connects a new remote player to the seat, forwarding its game events to `events`
+    fn remote_player(
+        &mut self,
+        seat_id: SeatId,
+        name: String,
+        settings: &RoomSettings,
+    ) -> RemotePlayer {
+        let (event_tx, mut event_rx) = unbounded_channel::<GameEvent>();
+        let (action_tx, action_rx) = unbounded_channel::<Answer>();
+        if let Some(seat) = self.seats.get_mut(&seat_id) {
+            seat.actions = action_tx;
+        }
+        let mut player = RemotePlayer::new(name, event_tx, action_rx);
+        if let Some(secs) = settings.turn_timeout_secs {
+            player.set_turn_timeout(Duration::from_secs(secs), settings.timeout_action);
+        }
+
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                if events
+                    .send(SessionEvent::Seat(seat_id, event))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        player
+    }
+
+    /// Seats the members of the room and plays the match in a task of its own. Game events of
+    /// every seat and the views of the spectators are forwarded to `events`.
+    fn start_game(&mut self, room_key: &str) {
+        let Some(room) = self.rooms.get(room_key) else {
+            return;
+        };
+        let room_name = room.name.clone();
+        let members = room.members.clone();
+        let settings = room.settings.clone();
+        let bots = (0..room.bot_count())
+            .map(|_| computer_player(settings.bot_level, &self.rules))
+            .collect_vec();
+
+        let mut humans = vec![];
+        for (id, name) in members {
+            let seat_id = self.next_seat;
+            self.next_seat += 1;
+            let username = match self.clients.get_mut(&id) {
+                Some(client) => {
+                    client.seat = Some(seat_id);
+                    client.name.clone()
+                }
+                None => name.clone(),
+            };
+            let token = seat_token(&mut rand::thread_rng());
+            if self.clients.contains_key(&id) {
+                self.send(id, GameEvent::Seated(token.clone()));
+            }
+            self.seats.insert(
+                seat_id,
+                Seat {
+                    room: room_key.to_string(),
+                    name: name.clone(),
+                    username,
+                    token,
+                    member: id,
+                    actions: unbounded_channel().0,
+                    snapshot: NotifyEvent::default(),
+                    pending_action: None,
+                    asked_at: Instant::now(),
+                    disconnected_since: None,
+                    taken_over: false,
+                },
+            );
+            humans.push(self.remote_player(seat_id, name, &settings));
+        }
+        let (swap_tx, swap_rx) = unbounded_channel();
+        self.games.insert(
+            room_key.to_string(),
+            Game {
+                settings: settings.clone(),
+                swaps: swap_tx,
+                public: NotifyEvent::default(),
+                omniscient: NotifyEvent::default(),
+                full: NotifyEvent::default(),
+            },
+        );
+
+        let (spectator_tx, mut spectator_rx) = unbounded_channel();
+        let name = room_key.to_string();
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            while let Some(update) = spectator_rx.recv().await {
+                let event = SessionEvent::Spectators(name.clone(), update);
+                if events.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let name = room_key.to_string();
+        let events = self.events.clone();
+        let seed = self.next_seed();
+        let rules = self.rules.clone();
+        let record_path = self
+            .record_dir
+            .as_ref()
+            .map(|dir| dir.join(record_file_name(&room_name, seed, SystemTime::now())));
+        tokio::spawn(async move {
+            let game = tokio::spawn(run_game(
+                rules,
+                humans,
+                bots,
+                swap_rx,
+                spectator_tx,
+                seed,
+                record_path,
+            ));
+            let result = game.await.map_err(GameError::from);
+            _ = events.send(SessionEvent::GameFinished(name, result)).await;
+        });
+    }
+
+    /// Games are seeded one after another from the configured seed, or randomly without one
+    fn next_seed(&mut self) -> u64 {
+        let seed = self
+            .seed
+            .map_or_else(rand::random, |seed| seed.wrapping_add(self.games_started));
+        self.games_started += 1;
+        seed
+    }
+}
+
+/// Random letters and digits, too many to be guessed
+fn seat_token(rng: &mut impl Rng) -> String {
+    rng.sample_iter(Alphanumeric).take(32).collect()
+}
+
+/// Computer players think on the blocking thread pool, so searching players do not stall the
+/// server. Searching players simulate the game by the rules of the table.
+pub fn computer_player(level: BotLevel, rules: &RuleSet) -> Box<dyn AsyncPlayer> {
+    match level {
+        BotLevel::Easy => Box::new(BlockingPlayer::new(RandomPlayingComputer::new())),
+        BotLevel::Medium => Box::new(BlockingPlayer::new(CardCountingComputer::new())),
+        BotLevel::Hard => {
+            let mut player = IsmctsComputer::new();
+            player.rules = rules.clone();
+            Box::new(BlockingPlayer::new(player))
+        }
+    }
+}
+
+/// Records are named after the start, the seed and the room of the game
+fn record_file_name(room: &str, seed: u64, started: SystemTime) -> String {
+    let secs = started
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let room = room
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    format!("game_{}_{}_{}.json", secs, seed, room)
+}
+
+pub async fn run_game(
+    rules: RuleSet,
+    humans: Vec<RemotePlayer>,
+    bots: Vec<Box<dyn AsyncPlayer>>,
+    swaps: UnboundedReceiver<SeatSwap>,
+    spectators: UnboundedSender<SpectatorUpdate>,
+    seed: u64,
+    record_path: Option<PathBuf>,
+) {
+    let mut lobby = GameLobby::new(rules, seed);
+    println!("Starting game with seed {}", lobby.seed());
+    if let Some(record_path) = record_path {
+        let record_dir = record_path.parent().unwrap_or(Path::new("."));
+        match std::fs::create_dir_all(record_dir) {
+            Ok(_) => lobby.record_to(record_path),
+            Err(e) => println!("Could not create directory for game records: {}", e),
+        }
+    }
+    for human in humans {
+        lobby.add_player(|| human);
+    }
+    for bot in bots {
+        lobby.add_player(|| bot);
+    }
+    lobby.swap_seats_from(swaps);
+    lobby.broadcast_to(spectators);
+    lobby.run_match().await;
+    println!(
+        "Game with seed {} finished after {} rounds",
+        lobby.seed(),
+        lobby.record().rounds.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sessions::{record_file_name, GameError, SessionError, SessionEvent, Sessions};
+    use renet::ClientId;
+    use royals_core::events::{ActionError, ClientEvent, Event, GameEvent, RoomSettings};
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    fn client(id: u64) -> ClientId {
+        ClientId::from_raw(id)
+    }
+
+    #[tokio::test]
+    async fn reconnect_should_resync_the_seat() {
+        let (mut sessions, mut events) = started_game(false);
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+        let username = sessions.clients[&member].name.clone();
+
+        sessions.disconnect(member);
+        sessions.outbox();
+        sessions.connect(client(3), username);
+
+        let outbox = sessions.outbox();
+        assert_eq!(sessions.seats[&question].member, client(3));
+        assert!(outbox
+            .iter()
+            .any(|(id, e)| *id == client(3) && matches!(e, GameEvent::RoomUpdate(_))));
+        assert!(matches!(
+            outbox.last(),
+            Some((id, GameEvent::ObtainAction(_))) if *id == client(3)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reclaim_should_take_the_seat_of_the_token() {
+        let (mut sessions, mut events) = started_game(false);
+        sessions.trusted_usernames = false;
+        let outbox = sessions.outbox();
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+        let username = sessions.clients[&member].name.clone();
+        let token = sessions.seats[&question].token.clone();
+        assert!(outbox
+            .iter()
+            .any(|(id, e)| *id == member && matches!(e, GameEvent::Seated(t) if *t == token)));
+
+        sessions.disconnect(member);
+        sessions.connect(client(3), username);
+
+        // the username alone can not be trusted
+        assert_eq!(sessions.seats[&question].member, member);
+        assert!(sessions.clients[&client(3)].seat.is_none());
+
+        let wrong = ClientEvent::Reclaim("wrong".to_string());
+        sessions.handle_client_event(client(3), wrong).unwrap();
+        assert!(sessions.clients[&client(3)].seat.is_none());
+
+        sessions
+            .handle_client_event(client(3), ClientEvent::Reclaim(token))
+            .unwrap();
+        assert_eq!(sessions.seats[&question].member, client(3));
+        assert_eq!(sessions.clients[&client(3)].seat, Some(question));
+    }
+
+    #[tokio::test]
+    async fn disconnect_should_hand_the_seat_to_a_computer_player() {
+        let (mut sessions, mut events) = started_game(true);
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+        let username = sessions.clients[&member].name.clone();
+
+        sessions.disconnect(member);
+
+        // the game goes on with the other player
+        let next = next_question(&mut sessions, &mut events).await;
+        assert_ne!(next, question);
+        assert!(sessions.seats[&question].taken_over);
+
+        sessions.connect(client(3), username);
+        assert!(!sessions.seats[&question].taken_over);
+    }
+
+    #[tokio::test]
+    async fn leave_room_should_release_the_seat() {
+        let (mut sessions, mut events) = started_game(false);
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+
+        sessions
+            .handle_client_event(member, ClientEvent::LeaveRoom)
+            .unwrap();
+
+        assert!(!sessions.seats.contains_key(&question));
+        assert!(sessions.rooms.room_of(member).is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_actions_should_be_rejected_and_asked_again() {
+        let (mut sessions, mut events) = started_game(false);
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+        let Some(GameEvent::ObtainAction(pending)) = &sessions.seats[&question].pending_action
+        else {
+            panic!("the game is waiting for an action");
+        };
+        let invalid = ClientEvent::Action(pending.turn, 99);
+
+        sessions.handle_client_event(member, invalid).unwrap();
+
+        assert_eq!(next_question(&mut sessions, &mut events).await, question);
+        assert!(sessions.outbox().iter().any(|(id, e)| *id == member
+            && matches!(e, GameEvent::ActionRejected(ActionError::Invalid(99)))));
+        assert_eq!(
+            sessions.handle_client_event(client(9), ClientEvent::ListRooms),
+            Err(SessionError::UnknownClient(client(9)))
+        );
+    }
+
+    #[tokio::test]
+    async fn corrected_answers_should_be_passed_on_as_well() {
+        let (mut sessions, mut events) = started_game(false);
+        let question = next_question(&mut sessions, &mut events).await;
+        let member = sessions.seats[&question].member;
+        let Some(GameEvent::ObtainAction(pending)) = &sessions.seats[&question].pending_action
+        else {
+            panic!("the game is waiting for an action");
+        };
+        let turn = pending.turn;
+
+        sessions
+            .handle_client_event(member, ClientEvent::Action(turn, 99))
+            .unwrap();
+        sessions
+            .handle_client_event(member, ClientEvent::Action(turn, 0))
+            .unwrap();
+
+        assert!(sessions.seats[&question].pending_action.is_some());
+        assert!(!sessions
+            .outbox()
+            .iter()
+            .any(|(_, e)| matches!(e, GameEvent::ActionRejected(ActionError::NotYourTurn))));
+    }
+
+    #[tokio::test]
+    async fn aborted_games_should_be_reported_to_the_room() {
+        let (mut sessions, _events) = started_game(false);
+        sessions.outbox();
+
+        let error = GameError::Panicked("boom".to_string());
+        let room = sessions.games.keys().next().unwrap().clone();
+        sessions.handle_event(SessionEvent::GameFinished(room, Err(error)));
+
+        let outbox = sessions.outbox();
+        for id in [client(1), client(2)] {
+            assert!(outbox
+                .iter()
+                .any(|(to, e)| *to == id && matches!(e, GameEvent::RoomRejected(_))));
+        }
+        assert!(sessions.seats.is_empty());
+    }
+
+    #[tokio::test]
+    async fn spectators_should_watch_without_seeing_hands() {
+        let (mut sessions, mut events) = started_game(false);
+        sessions.connect(client(3), "Baz".to_string());
+        let spectate = ClientEvent::Spectate("table".to_string(), false);
+        sessions.handle_client_event(client(3), spectate).unwrap();
+        let question = next_question(&mut sessions, &mut events).await;
+
+        // actions of spectators go nowhere
+        sessions
+            .handle_client_event(client(3), ClientEvent::Action(0, 0))
+            .unwrap();
+        assert!(sessions.seats[&question].pending_action.is_some());
+
+        // playing on rather than giving up keeps the round and its hands going
+        let seat = &sessions.seats[&question];
+        let Some(GameEvent::ObtainAction(pending)) = &seat.pending_action else {
+            panic!("the game is waiting for an action");
+        };
+        let action = ClientEvent::Action(pending.turn, pending.valid_actions.len() - 1);
+        sessions.handle_client_event(seat.member, action).unwrap();
+        loop {
+            let event = events.recv().await.unwrap();
+            let is_view = matches!(event, SessionEvent::Spectators(..));
+            sessions.handle_event(event);
+            if is_view {
+                break;
+            }
+        }
+
+        let outbox = sessions.outbox();
+        assert!(outbox.iter().any(|(id, e)| *id == client(3)
+            && matches!(e, GameEvent::ActionRejected(ActionError::NotYourTurn))));
+        let view = outbox
+            .into_iter()
+            .rev()
+            .find_map(|(id, e)| match e {
+                GameEvent::Notify(notify) if id == client(3) => Some(notify),
+                _ => None,
+            })
+            .unwrap();
+        assert!(view
+            .game_log
+            .iter()
+            .all(|e| !matches!(e, Event::PickUp(_, Some(_), _))));
+    }
+
+    #[test]
+    fn record_file_name_should_name_start_seed_and_room() {
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+
+        assert_eq!(
+            record_file_name("Foo's table", 42, started),
+            "game_1000_42_Foo_s_table.json"
+        );
+    }
+
+    #[test]
+    fn games_should_be_seeded_one_after_another() {
+        let (event_tx, _event_rx) = channel(64);
+        let mut sessions = Sessions::new(event_tx, Some(u64::MAX));
+
+        let seeds = [sessions.next_seed(), sessions.next_seed()];
+
+        assert_eq!(seeds, [u64::MAX, 0]);
+    }
+
+    // Infra ----------------------------------------------------------------------------------
+
+    fn started_game(bot_takeover: bool) -> (Sessions, Receiver<SessionEvent>) {
+        let (event_tx, event_rx) = channel(64);
+        let mut sessions = Sessions::new(event_tx, Some(42));
+        sessions.trust_usernames();
+        sessions.connect(client(1), "Foo".to_string());
+        sessions.connect(client(2), "Bar".to_string());
+        let settings = RoomSettings {
+            seats: 2,
+            fill_with_bots: false,
+            bot_takeover,
+            ..RoomSettings::default()
+        };
+        let create = ClientEvent::CreateRoom("table".to_string(), settings);
+        sessions.handle_client_event(client(1), create).unwrap();
+        let join = ClientEvent::JoinRoom("table".to_string());
+        sessions.handle_client_event(client(2), join).unwrap();
+        sessions
+            .handle_client_event(client(1), ClientEvent::StartGame)
+            .unwrap();
+        (sessions, event_rx)
+    }
+
+    async fn next_question(sessions: &mut Sessions, events: &mut Receiver<SessionEvent>) -> u64 {
+        loop {
+            let event = events.recv().await.unwrap();
+            let question = match &event {
+                SessionEvent::Seat(seat, GameEvent::ObtainAction(_)) => Some(*seat),
+                _ => None,
+            };
+            sessions.handle_event(event);
+            if let Some(seat) = question {
+                return seat;
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{game_lobby::GameLobby, game_logic::GameState, rule_set::RuleSet};
+use itertools::Itertools;
+use royals_core::events::{Event, PlayerId};
+use std::{collections::HashMap, thread};
+
+#[derive(Default, Debug)]
+pub struct SimulationStats {
+    pub games: usize,
+    pub rounds: usize,
+    pub turns: usize,
+    /// Won matches per player name, shared wins are counted for every winner
+    pub wins: HashMap<String, usize>,
+    /// Eliminations per reason of the fold that made the player drop out
+    pub eliminations: HashMap<String, usize>,
+}
+
+impl SimulationStats {
+    pub fn add_match(&mut self, lobby: &GameLobby, winners: &[PlayerId]) {
+        let names = lobby.player_names();
+        for &winner in winners {
+            *self.wins.entry(names[winner].clone()).or_default() += 1;
+        }
+
+        let record = lobby.record();
+        self.games += 1;
+        self.rounds += record.rounds.len();
+        self.turns += record.rounds.iter().map(|r| r.actions.len()).sum::<usize>();
+
+        let mut fold_reasons: HashMap<PlayerId, String> = HashMap::new();
+        for event in GameState::filter_event(lobby.game_log(), None) {
+            match event {
+                Event::Fold(player, _, reason) => {
+                    fold_reasons.insert(player, reason);
+                }
+                Event::DropOut(player) => {
+                    let reason = fold_reasons.remove(&player).unwrap_or_default();
+                    *self.eliminations.entry(reason).or_default() += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: SimulationStats) {
+        self.games += other.games;
+        self.rounds += other.rounds;
+        self.turns += other.turns;
+        for (name, wins) in other.wins {
+            *self.wins.entry(name).or_default() += wins;
+        }
+        for (reason, count) in other.eliminations {
+            *self.eliminations.entry(reason).or_default() += count;
+        }
+    }
+
+    pub fn win_rate(&self, name: &str) -> f64 {
+        *self.wins.get(name).unwrap_or(&0) as f64 / self.games.max(1) as f64
+    }
+
+    pub fn average_round_length(&self) -> f64 {
+        self.turns as f64 / self.rounds.max(1) as f64
+    }
+}
+
+/// Plays the given number of matches spread over several threads. Every match is set up by
+/// `seat_players` and seeded with `seed` plus the number of the match.
+pub fn simulate<F>(
+    games: usize,
+    threads: usize,
+    seed: u64,
+    rules: &RuleSet,
+    seat_players: F,
+) -> SimulationStats
+where
+    F: Fn(&mut GameLobby) + Sync,
+{
+    let threads = threads.max(1);
+    thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|t| {
+                let seat_players = &seat_players;
+                scope.spawn(move || {
+                    let mut stats = SimulationStats::default();
+                    for game in (t..games).step_by(threads) {
+                        let mut lobby =
+                            GameLobby::new(rules.clone(), seed.wrapping_add(game as u64));
+                        seat_players(&mut lobby);
+                        let winners = lobby.play_match();
+                        stats.add_match(&lobby, &winners);
+                    }
+                    stats
+                })
+            })
+            .collect_vec();
+
+        let mut stats = SimulationStats::default();
+        for handle in handles {
+            stats.merge(handle.join().unwrap());
+        }
+        stats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        player::Player, random_playing_computer::RandomPlayingComputer, rule_set::RuleSet,
+        simulation::simulate,
+    };
+
+    #[test]
+    fn simulate_should_play_the_requested_number_of_games() {
+        let stats = simulate(5, 2, 0, &RuleSet::default(), |lobby| {
+            for name in ["A", "B", "C"] {
+                lobby.add_player(|| {
+                    let mut player = RandomPlayingComputer::new();
+                    player.data_mut().set_name(name.to_string());
+                    player
+                });
+            }
+        });
+
+        assert_eq!(stats.games, 5);
+        assert!(stats.wins.values().sum::<usize>() >= 5);
+        assert!(stats.rounds >= 5);
+        assert!(stats.eliminations.values().sum::<usize>() > 0);
+    }
+}
@@ -2,21 +2,34 @@ use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 use bevy_renet::{
     renet::{
-        transport::{ClientAuthentication, NetcodeClientTransport},
+        transport::{ClientAuthentication, ConnectToken, NetcodeClientTransport},
         ConnectionConfig, DefaultChannel, RenetClient,
     },
     transport::NetcodeClientPlugin,
     RenetClientPlugin,
 };
-use royals_core::{events::GameEvent, user_name::Username};
+use royals_core::{
+    events::{BotLevel, ClientEvent, GameEvent, NotifyEvent, ObtainActionEvent, RoomInfo},
+    protocol::{decode, encode, ClientMessage, Codec, Message, CODECS, PROTOCOL_ID},
+    user_name::Username,
+};
 use std::{
+    fs::{self, File},
     net::{SocketAddr, UdpSocket},
-    time::SystemTime,
+    time::{Instant, SystemTime},
 };
 use ui::{ui_system, ClientEventComponent};
 
 pub mod ui;
 
+/// Keeps the token of the seat in a running game, to reclaim it after the client was restarted
+const SEAT_TOKEN_FILE: &str = "royals_seat.token";
+
+fn read_connect_token(token_file: &str) -> Result<ConnectToken, String> {
+    let mut file = File::open(token_file).map_err(|e| e.to_string())?;
+    ConnectToken::read(&mut file).map_err(|e| e.to_string())
+}
+
 fn main() {
     let server_addr: SocketAddr = "127.0.0.1:6969".parse().unwrap();
     let username = Username::from_string("bevy".to_string());
@@ -28,11 +41,21 @@ fn main() {
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
     let client_id = current_time.as_millis() as u64;
-    let authentication = ClientAuthentication::Unsecure {
-        server_addr,
-        client_id,
-        user_data: Some(username.to_netcode_user_data()),
-        protocol_id: 0,
+    // servers with a private key only accept the tokens they issued, which carry the username
+    let authentication = match std::env::var("ROYALS_CONNECT_TOKEN") {
+        Ok(token_file) => match read_connect_token(&token_file) {
+            Ok(connect_token) => ClientAuthentication::Secure { connect_token },
+            Err(e) => {
+                println!("Could not read connect token {}: {}", token_file, e);
+                std::process::exit(1);
+            }
+        },
+        Err(_) => ClientAuthentication::Unsecure {
+            server_addr,
+            client_id,
+            user_data: username.to_netcode_user_data().ok(),
+            protocol_id: PROTOCOL_ID,
+        },
     };
 
     let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
@@ -46,8 +69,9 @@ fn main() {
         // -----------------------------------------------------
         .insert_resource(client)
         .insert_resource(transport)
-        .insert_resource(GameState { last_event: None })
+        .insert_resource(GameState::default())
         // -----------------------------------------------------
+        .add_systems(Startup, hello_system)
         .add_systems(Update, send_message_system)
         .add_systems(Update, receive_message_system)
         .add_systems(Update, ui_system)
@@ -55,28 +79,98 @@ fn main() {
         .run();
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct GameState {
-    pub last_event: Option<GameEvent>,
+    /// Players and log of the game, built up from the snapshots and updates of the server
+    pub game: NotifyEvent,
+    pub pending_action: Option<ObtainActionEvent>,
+    /// Local time at which the server chooses the pending action on behalf of the player
+    pub deadline: Option<Instant>,
+    pub rooms: Vec<RoomInfo>,
+    pub room: Option<RoomInfo>,
+    pub room_message: Option<String>,
+    pub room_name: String,
+    pub private_room: bool,
+    pub turn_timer: bool,
+    pub bot_level: BotLevel,
+    pub room_code: String,
+    /// Spectators may watch with every hand revealed
+    pub casting: bool,
+    /// Encoding of the messages the server chose during the handshake
+    pub codec: Codec,
+    /// The server only takes events once it welcomed the client
+    pub welcomed: bool,
+}
+
+/// The server lets the client in once it knows the protocol version
+fn hello_system(mut client: ResMut<RenetClient>) {
+    let hello: ClientMessage = Message::Hello(CODECS.to_vec());
+    client.send_message(DefaultChannel::ReliableOrdered, encode(Codec::Json, &hello));
 }
 
 fn send_message_system(
     mut commands: Commands,
     query: Query<(Entity, &ClientEventComponent)>,
     mut client: ResMut<RenetClient>,
+    game_state: Res<GameState>,
 ) {
+    // events wait until the codec is known
+    if !game_state.welcomed {
+        return;
+    }
     for (entity, client_event) in query.iter() {
-        let text = serde_json::to_string(&client_event.e).unwrap();
-        client.send_message(DefaultChannel::ReliableOrdered, text.as_bytes().to_vec());
+        let message = Message::Event(client_event.e.clone());
+        let bytes = encode(game_state.codec, &message);
+        client.send_message(DefaultChannel::ReliableOrdered, bytes);
         commands.entity(entity).despawn();
     }
 }
 
 fn receive_message_system(mut client: ResMut<RenetClient>, mut game_state: ResMut<GameState>) {
     while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
-        let message = String::from_utf8(message.into()).unwrap();
-        if let Ok(event) = serde_json::from_str::<GameEvent>(&message) {
-            game_state.as_mut().last_event = Some(event);
+        let game_state = game_state.as_mut();
+        match decode::<GameEvent>(game_state.codec, &message) {
+            Ok((_, Message::Event(event))) => match event {
+                GameEvent::RoomList(rooms) => {
+                    game_state.rooms = rooms;
+                    game_state.room = None;
+                }
+                GameEvent::RoomUpdate(room) => {
+                    game_state.room = Some(room);
+                    game_state.room_message = None;
+                }
+                GameEvent::RoomRejected(reason) => game_state.room_message = Some(reason),
+                GameEvent::ActionRejected(error) => {
+                    game_state.room_message = Some(error.to_string())
+                }
+                GameEvent::Seated(token) => {
+                    if let Err(e) = fs::write(SEAT_TOKEN_FILE, token) {
+                        game_state.room_message = Some(format!("Could not keep the seat: {}", e));
+                    }
+                }
+                GameEvent::ObtainAction(action) => {
+                    game_state.deadline = action.time_left.map(|left| Instant::now() + left);
+                    game_state.pending_action = Some(action);
+                }
+                event => {
+                    game_state.game.apply(&event);
+                    game_state.pending_action = None;
+                }
+            },
+            Ok((_, Message::Welcome(codec))) => {
+                game_state.codec = codec;
+                game_state.welcomed = true;
+                // back at the seat of a game the connection was lost in, the server hands out
+                // the token again
+                if let Ok(token) = fs::read_to_string(SEAT_TOKEN_FILE) {
+                    _ = fs::remove_file(SEAT_TOKEN_FILE);
+                    let reclaim = Message::Event(ClientEvent::Reclaim(token));
+                    client.send_message(DefaultChannel::ReliableOrdered, encode(codec, &reclaim));
+                }
+            }
+            Ok((_, Message::Rejected(reason))) => game_state.room_message = Some(reason),
+            Err(e) => game_state.room_message = Some(e.to_string()),
+            Ok(_) => {}
         }
     }
 }
@@ -1,21 +1,26 @@
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_egui::{
-    egui::{ScrollArea, SidePanel},
+    egui::{ScrollArea, SidePanel, Ui},
     EguiContexts,
 };
 use itertools::Itertools;
 use royals_core::{
     events,
-    events::{Action, ClientEvent, Play},
+    events::{Action, BotLevel, ClientEvent, Play, RoomSettings},
 };
+use std::time::Instant;
 
 #[derive(Component)]
 pub struct ClientEventComponent {
     pub e: ClientEvent,
 }
 
-pub fn ui_system(mut commands: Commands, mut contexts: EguiContexts, game_state: Res<GameState>) {
+pub fn ui_system(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut game_state: ResMut<GameState>,
+) {
     let egui_context = contexts.ctx_mut();
     SidePanel::left("left_panel")
         .min_width(600.0)
@@ -24,14 +29,23 @@ pub fn ui_system(mut commands: Commands, mut contexts: EguiContexts, game_state:
             ui.vertical(|ui| {
                 ui.label("Royals Bevy debug UI\n");
 
+                room_ui(ui, &mut commands, &mut game_state);
+
                 ScrollArea::vertical().drag_to_scroll(true).show(ui, |ui| {
-                    if let Some(royals_core::events::GameEvent::ObtainAction(o)) =
-                        &game_state.last_event
-                    {
-                        for (i, action) in o.valid_actions.iter().enumerate() {
-                            if ui.button(action_to_string(action, &o.players)).clicked() {
+                    if let Some(o) = &game_state.pending_action {
+                        if let Some(left) = game_state
+                            .deadline
+                            .map(|d| d.saturating_duration_since(Instant::now()))
+                        {
+                            ui.label(format!("Time left: {} s", left.as_secs()));
+                        }
+                        for action in &o.valid_actions {
+                            if ui
+                                .button(action_to_string(action, &game_state.game.players))
+                                .clicked()
+                            {
                                 commands.spawn(ClientEventComponent {
-                                    e: ClientEvent { action_id: i },
+                                    e: ClientEvent::Submit(o.turn, action.clone()),
                                 });
                             }
                         }
@@ -46,33 +60,126 @@ pub fn ui_system(mut commands: Commands, mut contexts: EguiContexts, game_state:
         .show(egui_context, |ui| {
             ui.vertical(|ui| {
                 ScrollArea::vertical().drag_to_scroll(true).show(ui, |ui| {
-                    if let Some(last_event) = &game_state.last_event {
-                        match last_event {
-                            royals_core::events::GameEvent::Notify(n) => {
-                                for player in &n.players {
-                                    ui.label(format!("Player: {}", player));
-                                }
-                                ui.label("----------------------------");
-                                for event in &n.game_log {
-                                    ui.label(format!("> {}", event_to_string(event, &n.players)));
-                                }
-                            }
-                            royals_core::events::GameEvent::ObtainAction(o) => {
-                                for player in &o.players {
-                                    ui.label(format!("Player: {}", player));
-                                }
-                                ui.label("----------------------------");
-                                for event in &o.game_log {
-                                    ui.label(format!("> {}", event_to_string(event, &o.players)));
-                                }
-                            }
-                        }
+                    let game = &game_state.game;
+                    for player in &game.players {
+                        ui.label(format!("Player: {}", player));
+                    }
+                    ui.label("----------------------------");
+                    for event in &game.game_log {
+                        ui.label(format!("> {}", event_to_string(event, &game.players)));
                     }
                 });
             });
         });
 }
 
+fn room_ui(ui: &mut Ui, commands: &mut Commands, game_state: &mut GameState) {
+    let mut send = |e: ClientEvent| {
+        commands.spawn(ClientEventComponent { e });
+    };
+    if let Some(message) = &game_state.room_message {
+        ui.label(format!("! {}", message));
+    }
+    match &game_state.room {
+        Some(room) => {
+            ui.label(format!("Room {} hosted by {}", room.name, room.host));
+            if let Some(code) = &room.code {
+                ui.label(format!(
+                    "Private room, share the code {} to invite players",
+                    code
+                ));
+            }
+            ui.label(format!(
+                "Players: {} ({} seats)",
+                room.players.join(", "),
+                room.settings.seats
+            ));
+            if !room.spectators.is_empty() {
+                ui.label(format!("Spectators: {}", room.spectators.join(", ")));
+            }
+            if room.spectating {
+                if ui.button("Stop watching").clicked() {
+                    send(ClientEvent::LeaveRoom);
+                }
+            } else if !room.started {
+                ui.horizontal(|ui| {
+                    if ui.button("Start game").clicked() {
+                        send(ClientEvent::StartGame);
+                    }
+                    if ui.button("Leave room").clicked() {
+                        send(ClientEvent::LeaveRoom);
+                    }
+                });
+            }
+        }
+        None => {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut game_state.room_name);
+                ui.checkbox(&mut game_state.private_room, "private");
+                ui.checkbox(&mut game_state.turn_timer, "60 s per turn");
+                ui.checkbox(
+                    &mut game_state.casting,
+                    "show hands to spectators after 30 s",
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Computer players:");
+                ui.radio_value(&mut game_state.bot_level, BotLevel::Easy, "easy");
+                ui.radio_value(&mut game_state.bot_level, BotLevel::Medium, "medium");
+                ui.radio_value(&mut game_state.bot_level, BotLevel::Hard, "hard");
+                if ui.button("Create room").clicked() {
+                    send(ClientEvent::CreateRoom(
+                        game_state.room_name.clone(),
+                        RoomSettings {
+                            private: game_state.private_room,
+                            turn_timeout_secs: game_state.turn_timer.then_some(60),
+                            bot_level: game_state.bot_level,
+                            omniscient_delay_secs: game_state.casting.then_some(30),
+                            ..RoomSettings::default()
+                        },
+                    ));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut game_state.room_code);
+                if ui.button("Join with code").clicked() {
+                    send(ClientEvent::JoinWithCode(game_state.room_code.clone()));
+                }
+            });
+            let mut spectate = None;
+            for room in &game_state.rooms {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} ({}/{}){}",
+                        room.name,
+                        room.players.len(),
+                        room.settings.seats,
+                        if room.started { " playing" } else { "" }
+                    ));
+                    if !room.started && ui.button("Join").clicked() {
+                        send(ClientEvent::JoinRoom(room.name.clone()));
+                    }
+                    if ui.button("Watch").clicked() {
+                        spectate = Some((room.name.clone(), false));
+                    }
+                    if room.settings.omniscient_delay_secs.is_some()
+                        && ui.button("Watch all hands").clicked()
+                    {
+                        spectate = Some((room.name.clone(), true));
+                    }
+                });
+            }
+            if let Some((room, omniscient)) = spectate {
+                send(ClientEvent::Spectate(room, omniscient));
+            }
+            if ui.button("Refresh").clicked() {
+                send(ClientEvent::ListRooms);
+            }
+        }
+    }
+    ui.label("----------------------------");
+}
+
 fn event_to_string(event: &events::Event, players: &[String]) -> String {
     match event {
         events::Event::Play(id, play) => format!("{} plays {:?}", players[*id], play),
@@ -90,12 +197,48 @@ fn event_to_string(event: &events::Event, players: &[String]) -> String {
                 card_op.map(|c| c.to_string()).unwrap_or("?".to_string())
             )
         }
-        events::Event::Winner(ids) => {
+        events::Event::Winner(ids, discarded) => {
             format!(
-                "Winner(s): {}",
+                "Winner(s): {} (folded values: {})",
+                ids.iter().map(|id| players[*id].clone()).join(" "),
+                discarded
+                    .iter()
+                    .enumerate()
+                    .map(|(id, value)| format!("{} {}", players[id], value))
+                    .join(", ")
+            )
+        }
+        events::Event::MatchStarted(seed) => format!("Match started (seed {})", seed),
+        events::Event::RoundStarted(round, id, seed) => {
+            format!(
+                "Round {} started by {} (seed {})",
+                round, players[*id], seed
+            )
+        }
+        events::Event::TokenAwarded(id, tokens) => {
+            format!("{} receives a token ({} in total)", players[*id], tokens)
+        }
+        events::Event::MatchWinner(ids) => {
+            format!(
+                "Match winner(s): {}",
                 ids.iter().map(|id| players[*id].clone()).join(" ")
             )
         }
+        events::Event::BurnFaceDown(card_op) => format!(
+            "Burned card {}",
+            card_op.map(|c| c.to_string()).unwrap_or("?".to_string())
+        ),
+        events::Event::BurnFaceUp(card) => format!("Burned card {} (face up)", card),
+        events::Event::SeatTakenOver(id) => {
+            format!("A computer player takes over for {}", players[*id])
+        }
+        events::Event::SeatReturned(id) => format!("{} is back", players[*id]),
+        events::Event::TurnTimedOut(id) => {
+            format!(
+                "{} ran out of time, the next action is taken for them",
+                players[*id]
+            )
+        }
     }
 }
 
@@ -112,6 +255,9 @@ fn play_to_string(play: &Play, players: &[String]) -> String {
         play.opponent
             .map(|oponent_id| format!(" targeting player {}", players[oponent_id].clone()))
             .unwrap_or("".to_string()),
+        play.second_opponent
+            .map(|oponent_id| format!(" and player {}", players[oponent_id].clone()))
+            .unwrap_or("".to_string()),
         play.guess
             .map(|card| format!(" guessing card {}", card))
             .unwrap_or("".to_string()),